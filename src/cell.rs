@@ -0,0 +1,183 @@
+use chrono::{
+  NaiveDate, NaiveDateTime, NaiveTime, TimeDelta, Timelike,
+};
+
+// A single value read from a data file, keeping the type the source
+// gave it so that rendering and schema types can rely on it
+#[derive(Debug, Clone, Default, PartialEq)]
+pub enum Cell
+{
+  #[default]
+  Empty,
+  Text(String),
+  Int(i64),
+  Float(f64),
+  Bool(bool),
+  Date(NaiveDate),
+  DateTime(NaiveDateTime),
+  // time of day without a date
+  Time(NaiveTime),
+  // elapsed time such as `[h]:mm:ss` cells
+  Duration(TimeDelta),
+  // error value of a spreadsheet cell such as `#DIV/0!`
+  Error(String),
+}
+
+impl Cell
+{
+  // Dates without a time of day are kept as plain dates
+  pub fn from_datetime(datetime: NaiveDateTime) -> Self
+  {
+    if datetime.time().num_seconds_from_midnight() == 0
+       && datetime.time().nanosecond() == 0
+    {
+      Cell::Date(datetime.date())
+    } else {
+      Cell::DateTime(datetime)
+    }
+  }
+
+  pub fn is_empty(&self) -> bool
+  {
+    match self {
+      Cell::Empty => true,
+      Cell::Text(s) => s.trim().is_empty(),
+      _ => false,
+    }
+  }
+
+  pub fn is_error(&self) -> bool
+  {
+    matches!(self, Cell::Error(_))
+  }
+
+  // Numbers, or text that reads as a number
+  pub fn is_number(&self) -> bool
+  {
+    match self {
+      Cell::Int(_) | Cell::Float(_) => true,
+      Cell::Text(s) => s.trim().parse::<f64>().is_ok(),
+      _ => false,
+    }
+  }
+}
+
+impl std::fmt::Display for Cell
+{
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>)
+         -> std::fmt::Result
+  {
+    match self {
+      Cell::Empty => Ok(()),
+      Cell::Text(s) | Cell::Error(s) => write!(f, "{}", s),
+      Cell::Int(i) => write!(f, "{}", i),
+      Cell::Float(x) => write!(f, "{}", format_float(*x)),
+      Cell::Bool(b) => write!(f, "{}", b),
+      // YYYY-MM-DD
+      Cell::Date(d) => write!(f, "{}", d.format("%Y-%m-%d")),
+      Cell::DateTime(t) => {
+        write!(f, "{}", t.format("%Y-%m-%d %H:%M:%S"))
+      }
+      Cell::Time(t) => write!(f, "{}", t.format("%H:%M:%S")),
+      Cell::Duration(d) => write!(f, "{}", format_duration(*d)),
+    }
+  }
+}
+
+impl From<&str> for Cell
+{
+  fn from(s: &str) -> Self
+  {
+    Cell::Text(s.to_string())
+  }
+}
+
+impl From<String> for Cell
+{
+  fn from(s: String) -> Self
+  {
+    Cell::Text(s)
+  }
+}
+
+// Up to 15 significant digits like spreadsheets show them, so that
+// 0.1 + 0.2 prints 0.3, and never in exponent notation
+pub fn format_float(x: f64) -> String
+{
+  if !x.is_finite() {
+    return x.to_string();
+  }
+  if x.fract() == 0.0 {
+    return format!("{:.0}", x);
+  }
+  let magnitude = x.abs().log10().floor() as i32 + 1;
+  let precision = (15 - magnitude).clamp(0, 324) as usize;
+  let s = format!("{:.*}", precision, x);
+  let s = s.trim_end_matches('0').trim_end_matches('.');
+  if s == "-0" {
+    String::from("0")
+  } else {
+    s.to_string()
+  }
+}
+
+// Hours keep counting past a day, e.g. 27:05:00
+pub fn format_duration(duration: TimeDelta) -> String
+{
+  let sign = if duration < TimeDelta::zero() { "-" } else { "" };
+  let secs = duration.num_seconds().unsigned_abs();
+  format!("{}{}:{:02}:{:02}",
+          sign,
+          secs / 3600,
+          secs / 60 % 60,
+          secs % 60)
+}
+
+// ISO 8601 durations as written by ods files, e.g. `PT10H30M0S` or
+// `P1DT2H`, years and months are not supported
+pub fn parse_iso_duration(s: &str) -> Option<TimeDelta>
+{
+  let (negative, s) = match s.strip_prefix('-') {
+    Some(rest) => (true, rest),
+    None => (false, s),
+  };
+  let s = s.strip_prefix('P')?;
+  let (days, time) = match s.split_once('T') {
+    Some((days, time)) => (days, time),
+    None => (s, ""),
+  };
+  let mut millis = 0f64;
+  if !days.is_empty() {
+    millis +=
+      days.strip_suffix('D')?.parse::<f64>().ok()? * 86_400_000.0;
+  }
+  let mut number = String::new();
+  for c in time.chars() {
+    let unit = match c {
+      'H' => 3_600_000.0,
+      'M' => 60_000.0,
+      'S' => 1000.0,
+      _ => {
+        number.push(c);
+        continue;
+      }
+    };
+    millis += number.parse::<f64>().ok()? * unit;
+    number.clear();
+  }
+  if !number.is_empty() {
+    return None;
+  }
+  let duration = millis_duration(millis)?;
+  Some(if negative { -duration } else { duration })
+}
+
+// None for values no TimeDelta holds instead of panicking
+pub fn millis_duration(millis: f64) -> Option<TimeDelta>
+{
+  let millis = millis.round();
+  if !millis.is_finite() || millis.abs() >= i64::MAX as f64 {
+    return None;
+  }
+  TimeDelta::try_milliseconds(millis as i64)
+}
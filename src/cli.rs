@@ -0,0 +1,457 @@
+use clap::{Args, Parser, Subcommand};
+use std::process::ExitCode;
+use std::sync::{Arc, Mutex};
+
+use crate::project::ProjectConfig;
+use crate::reader::{
+  detect_header, has_column_names, list_sheets, read_data,
+  CsvOptions, HeaderLayout, ReadOptions, SheetSelector,
+  TextEncoding,
+};
+use crate::schema_gen::{
+  infer_schema, schema_to_string, SAMPLE_ROWS,
+};
+use crate::schema_lint::{
+  lint_schema_file, unmapped_columns, LintIssue,
+};
+use crate::yaml_parser::*;
+use crate::{
+  default_jobs, process_data, process_sheets, Cell, ConvertOptions,
+  Db2MdError,
+};
+
+// Command line interface, the GUI is launched when no subcommand is
+// given so that double-clicking the binary keeps working
+#[derive(Debug, Parser)]
+#[command(name = "db2md",
+          version,
+          about = "Convert spreadsheets to Markdown files based on a \
+                   YAML schema")]
+pub struct Cli
+{
+  #[command(subcommand)]
+  pub command: Option<Command>,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum Command
+{
+  /// Launch the graphical interface (default without arguments)
+  Gui,
+  /// Convert every row of a spreadsheet into a Markdown file
+  Convert(ConvertArgs),
+  /// Check a schema for mistakes and that every field maps to a
+  /// column
+  Validate(ValidateArgs),
+  /// Print the sheet name, its size and the first rows
+  Inspect(InspectArgs),
+  /// Write a starter schema from the header and the first rows
+  InitSchema(InitSchemaArgs),
+}
+
+// Data file and the table to read from it, shared by all subcommands
+#[derive(Debug, Args)]
+pub struct InputArgs
+{
+  /// Data file: xlsx, xls, xlsb or ods workbook, csv, tsv, JSON or
+  /// SQLite
+  #[arg(long, visible_alias = "input")]
+  pub xlsx: String,
+  /// Sheet or SQLite table to read, by name or zero-based index
+  #[arg(long, default_value = "0")]
+  pub sheet: SheetSelector,
+  /// Field separator of csv files, `,` or tab for .tsv by default
+  #[arg(long, value_parser = parse_ascii)]
+  pub delimiter: Option<u8>,
+  /// Quote character of csv files, `"` by default
+  #[arg(long, value_parser = parse_ascii)]
+  pub quote: Option<u8>,
+  /// Encoding of csv files: auto, utf-8, gbk or utf-16
+  #[arg(long, default_value = "auto")]
+  pub encoding: TextEncoding,
+  /// SQL query to read from a SQLite database instead of a table
+  #[arg(long)]
+  pub query: Option<String>,
+}
+
+impl InputArgs
+{
+  pub fn read_options(&self) -> ReadOptions
+  {
+    ReadOptions { sheet: self.sheet.clone(),
+                  csv: CsvOptions { delimiter: self.delimiter,
+                                    quote: self.quote,
+                                    encoding: self.encoding },
+                  query: self.query.clone() }
+  }
+}
+
+// Single ASCII character, `tab` or `\t` for a tab
+fn parse_ascii(s: &str) -> Result<u8, String>
+{
+  match s {
+    "tab" | "\\t" | "\t" => Ok(b'\t'),
+    _ if s.len() == 1 && s.is_ascii() => Ok(s.as_bytes()[0]),
+    _ => {
+      Err(format!("expected a single ASCII character, got {}", s))
+    }
+  }
+}
+
+// Where the column titles are, shared by convert and validate
+#[derive(Debug, Args)]
+pub struct HeaderArgs
+{
+  /// The first row of the sheet holds column titles
+  #[arg(long)]
+  pub header: bool,
+  /// Guess from the data whether the first row holds column titles
+  #[arg(long, conflicts_with = "header")]
+  pub detect_header: bool,
+  /// Zero-based row of the header, rows above it are ignored; implies
+  /// --header
+  #[arg(long)]
+  pub header_row: Option<usize>,
+  /// Number of header rows merged into dotted titles such as
+  /// `customer.name`; implies --header
+  #[arg(long)]
+  pub header_rows: Option<usize>,
+  /// Rows to leave out between the header, or the top of the sheet,
+  /// and the data
+  #[arg(long, default_value_t = 0)]
+  pub skip_rows: usize,
+}
+
+impl HeaderArgs
+{
+  pub fn has_header(&self) -> bool
+  {
+    self.header
+    || self.header_row.is_some()
+    || self.header_rows.is_some()
+  }
+
+  pub fn layout(&self) -> HeaderLayout
+  {
+    HeaderLayout { row: self.header_row.unwrap_or(0),
+                   rows: self.header_rows.unwrap_or(1).max(1),
+                   skip: self.skip_rows }
+  }
+}
+
+#[derive(Debug, Args)]
+pub struct ConvertArgs
+{
+  #[command(flatten)]
+  pub input: InputArgs,
+  /// YAML schema describing the document structure
+  #[arg(long, required_unless_present = "project")]
+  pub schema: Option<String>,
+  /// Convert every sheet into its own subdirectory
+  #[arg(long)]
+  pub all_sheets: bool,
+  /// Convert only these sheets into their own subdirectories
+  #[arg(long, value_delimiter = ',')]
+  pub sheets: Vec<String>,
+  /// Project config binding sheets to schemas
+  #[arg(long)]
+  pub project: Option<String>,
+  /// Output directory for generated files
+  #[arg(long, default_value = "_md")]
+  pub out: String,
+  /// Prefix for generated files
+  #[arg(long, default_value = "ccms-doc")]
+  pub prefix: String,
+  #[command(flatten)]
+  pub header: HeaderArgs,
+  /// Number of worker threads, defaults to the number of cores
+  #[arg(long, short)]
+  pub jobs: Option<usize>,
+  /// Print the conversion report as JSON instead of a summary
+  #[arg(long)]
+  pub json: bool,
+}
+
+#[derive(Debug, Args)]
+pub struct ValidateArgs
+{
+  #[command(flatten)]
+  pub input: InputArgs,
+  /// YAML schema to validate
+  #[arg(long)]
+  pub schema: String,
+  #[command(flatten)]
+  pub header: HeaderArgs,
+}
+
+#[derive(Debug, Args)]
+pub struct InspectArgs
+{
+  #[command(flatten)]
+  pub input: InputArgs,
+  /// Number of rows to print
+  #[arg(long, default_value_t = 5)]
+  pub rows: usize,
+}
+
+#[derive(Debug, Args)]
+pub struct InitSchemaArgs
+{
+  #[command(flatten)]
+  pub input: InputArgs,
+  /// Zero-based row of the header, rows above it are ignored
+  #[arg(long, default_value_t = 0)]
+  pub header_row: usize,
+  /// Number of header rows merged into dotted titles
+  #[arg(long, default_value_t = 1)]
+  pub header_rows: usize,
+  /// Rows to leave out between the header and the data
+  #[arg(long, default_value_t = 0)]
+  pub skip_rows: usize,
+  /// Number of rows below the header to infer column types from
+  #[arg(long, default_value_t = SAMPLE_ROWS)]
+  pub sample_rows: usize,
+  /// Nest columns sharing a prefix, such as `customer_name` and
+  /// `customer_city`, into sections
+  #[arg(long)]
+  pub group: bool,
+  /// File to write the schema to, printed if not given
+  #[arg(long)]
+  pub out: Option<String>,
+  /// Overwrite the --out file if it exists
+  #[arg(long)]
+  pub force: bool,
+}
+
+// Run a non-GUI subcommand and turn its outcome into an exit code
+pub fn run(command: Command) -> ExitCode
+{
+  let res = match command {
+    Command::Gui => Ok(true),
+    Command::Convert(args) => convert(&args),
+    Command::Validate(args) => validate(&args),
+    Command::Inspect(args) => inspect(&args),
+    Command::InitSchema(args) => init_schema(&args),
+  };
+  match res {
+    Ok(true) => ExitCode::SUCCESS,
+    Ok(false) => ExitCode::FAILURE,
+    Err(e) => {
+      eprintln!("db2md: {}", e);
+      ExitCode::FAILURE
+    }
+  }
+}
+
+fn convert(args: &ConvertArgs) -> Result<bool, Db2MdError>
+{
+  let options =
+    ConvertOptions { md_prefix: args.prefix.clone(),
+                     output_dir: args.out.clone(),
+                     has_header: args.header.has_header(),
+                     detect_header: args.header.detect_header,
+                     header: args.header.layout(),
+                     read: args.input.read_options(),
+                     jobs: args.jobs.unwrap_or_else(default_jobs) };
+  let progress = Arc::new(Mutex::new(0f32));
+
+  let multi_sheet = args.all_sheets
+                    || args.project.is_some()
+                    || !args.sheets.is_empty();
+  let reports = if multi_sheet {
+    let mut project = match args.project.as_ref() {
+      Some(path) => ProjectConfig::load(path)?,
+      None => ProjectConfig::default(),
+    };
+    // --schema replaces the default schema of the project
+    if args.schema.is_some() {
+      project.schema = args.schema.clone();
+    }
+    if !args.sheets.is_empty() {
+      project.select(&args.sheets);
+    }
+    let xlsx = &args.input.xlsx;
+    let sheet_schemas = project.sheet_schemas(&list_sheets(xlsx)?)?;
+    process_sheets(xlsx, &sheet_schemas, &options, &progress)?
+  } else {
+    // clap requires --schema without --project
+    let schema = args.schema.clone().unwrap_or_default();
+    vec![process_data(&args.input.xlsx,
+                      &schema,
+                      &options,
+                      &progress)?]
+  };
+
+  let success = reports.iter().all(|r| r.is_success());
+  if args.json {
+    if multi_sheet {
+      let json = serde_json::to_string_pretty(&reports);
+      println!("{}", json.unwrap_or_default());
+    } else {
+      println!("{}", reports[0].to_json());
+    }
+    return Ok(success);
+  }
+  for report in reports.iter() {
+    if !report.invalid_fields.is_empty() {
+      eprintln!("db2md: invalid fields in {} {:?}",
+                report.schema, report.invalid_fields);
+    }
+    for failure in report.failures() {
+      if let Some(e) = failure.error.as_ref() {
+        eprintln!("db2md: {} row {}: {}",
+                  report.sheet, failure.row, e);
+      }
+    }
+    for row in report.rows.iter() {
+      for warning in row.warnings.iter() {
+        eprintln!("db2md: warning: {} row {}: {}",
+                  report.sheet, row.row, warning);
+      }
+    }
+    println!("db2md: wrote {} of {} rows from {} into {}",
+             report.written(),
+             report.rows.len(),
+             report.sheet,
+             report.output_dir);
+    if report.skipped() > 0 {
+      println!("db2md: skipped {} rows breaking the rules of {}",
+               report.skipped(),
+               report.schema);
+    }
+  }
+  Ok(success)
+}
+
+fn validate(args: &ValidateArgs) -> Result<bool, Db2MdError>
+{
+  let mut rows = vec![];
+  let (_, _, col_number) = read_data(&args.input.xlsx,
+                                     &args.input.read_options(),
+                                     &mut rows)?;
+  // the schema itself first, positions are those of its keys
+  let issues = lint_schema_file(&args.schema)?;
+  for issue in issues.iter() {
+    println!("db2md: {}", located(&args.schema, issue));
+  }
+  let lint_ok = !issues.iter().any(|i| i.is_error());
+  let Ok(schema) = Schema::load(&args.schema) else {
+    return Ok(false);
+  };
+  let fields = schema.fields();
+
+  let layout = args.header.layout();
+  let from = layout.row.min(rows.len());
+  let has_header = args.header.has_header()
+                   || has_column_names(&args.input.xlsx)
+                   || (args.header.detect_header
+                       && detect_header(&rows[from..]));
+  let headers =
+    if has_header { layout.headers(&rows) } else { Vec::new() };
+  let mut invalids = vec![];
+  let field_map =
+    map_fields_to_columns(&fields, &headers, &mut invalids);
+  let fallbacks = map_fallback_columns(&fields, &headers);
+
+  let field_num = field_map.len();
+  if field_num != col_number {
+    println!("db2md: find {} fields but each row has {} columns, only \
+              first {} will be used",
+             field_num,
+             col_number,
+             field_num.min(col_number));
+  }
+  for issue in
+    unmapped_columns(&field_map, &fallbacks, &headers, col_number)
+  {
+    println!("db2md: {}", issue);
+  }
+  if invalids.is_empty() {
+    println!("db2md: all {} fields in {} are found in the sheet",
+             field_num, args.schema);
+    Ok(lint_ok)
+  } else {
+    println!("db2md: invalid fields in {} {:?}",
+             args.schema, invalids);
+    for field in invalids.iter() {
+      let suggestions = suggest_headers(field, &headers);
+      if !suggestions.is_empty() {
+        println!("db2md: {} not found, did you mean {}?",
+                 field,
+                 quote_list(&suggestions));
+      }
+    }
+    Ok(false)
+  }
+}
+
+fn inspect(args: &InspectArgs) -> Result<bool, Db2MdError>
+{
+  let mut rows = vec![];
+  let (sheet_name, row_number, col_number) =
+    read_data(&args.input.xlsx,
+              &args.input.read_options(),
+              &mut rows)?;
+  println!("sheets: {}", list_sheets(&args.input.xlsx)?.join(", "));
+  println!("sheet: {}", sheet_name);
+  println!("rows: {}", row_number);
+  println!("columns: {}", col_number);
+  println!("header: {}",
+           if detect_header(&rows) { "likely" } else { "unlikely" });
+  for (idx, row) in rows.iter().take(args.rows).enumerate() {
+    let cells = row.iter().map(Cell::to_string).collect::<Vec<_>>();
+    println!("{:>4} | {}", idx, cells.join(" | "));
+  }
+  Ok(true)
+}
+
+fn init_schema(args: &InitSchemaArgs) -> Result<bool, Db2MdError>
+{
+  let mut rows = vec![];
+  read_data(&args.input.xlsx,
+            &args.input.read_options(),
+            &mut rows)?;
+  let layout = HeaderLayout { row: args.header_row,
+                              rows: args.header_rows.max(1),
+                              skip: args.skip_rows };
+  let schema =
+    infer_schema(&rows, &layout, args.sample_rows, args.group)?;
+  let yaml = schema_to_string(&schema)?;
+  match args.out.as_ref() {
+    Some(out) => {
+      if !args.force && std::path::Path::new(out).exists() {
+        eprintln!("db2md: {} exists, use --force to overwrite it",
+                  out);
+        return Ok(false);
+      }
+      std::fs::write(out, yaml).map_err(|e| Db2MdError::io(out, e))?;
+      println!("db2md: wrote schema of {} columns into {}",
+               layout.headers(&rows).len(),
+               out);
+    }
+    None => print!("{}", yaml),
+  }
+  Ok(true)
+}
+
+// `schema.yaml:4:3: error: ...` like compilers do
+fn located(file: &str, issue: &LintIssue) -> String
+{
+  match issue.position {
+    Some(_) => format!("{}:{}", file, issue),
+    None => format!("{}: {}", file, issue),
+  }
+}
+
+// 'a', 'b' or 'c'
+fn quote_list(items: &[String]) -> String
+{
+  let quoted =
+    items.iter().map(|s| format!("'{}'", s)).collect::<Vec<_>>();
+  match quoted.split_last() {
+    Some((last, rest)) if !rest.is_empty() => {
+      format!("{} or {}", rest.join(", "), last)
+    }
+    _ => quoted.join(""),
+  }
+}
@@ -0,0 +1,113 @@
+use serde::{Serialize, Serializer};
+use std::fmt;
+
+// Every failure db2md can report, either for a whole conversion or
+// for a single row in a ConversionReport
+#[derive(Debug, Clone, PartialEq)]
+pub enum Db2MdError
+{
+  // file system access, with the path involved
+  Io
+  {
+    path: String,
+    message: String,
+  },
+  // data file cannot be opened or a sheet cannot be read
+  Workbook(String),
+  // yaml schema cannot be read or parsed
+  Schema(String),
+  // schema fields cannot be bound to columns
+  Mapping(String),
+  // a row cannot be turned into markdown
+  Render(String),
+  // a cell does not hold the type its schema field declares
+  Coerce
+  {
+    field: String,
+    message: String,
+  },
+  // a cell holds a spreadsheet error value such as `#DIV/0!`
+  ErrorCell
+  {
+    field: String,
+    value: String,
+  },
+  // an `expr` field cannot be computed from the row
+  Expr
+  {
+    field: String,
+    message: String,
+  },
+  // a cell breaks a rule of its schema field, such as `enum`
+  Rule
+  {
+    field: String,
+    message: String,
+  },
+  // a row breaks rules and `on_invalid: fail` stops the conversion
+  InvalidRow
+  {
+    row: usize,
+    message: String,
+  },
+}
+
+impl Db2MdError
+{
+  pub fn io(path: &str, e: std::io::Error) -> Self
+  {
+    Db2MdError::Io { path: path.to_string(), message: e.to_string() }
+  }
+}
+
+impl fmt::Display for Db2MdError
+{
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result
+  {
+    match self {
+      Db2MdError::Io { path, message } => {
+        write!(f, "cannot access '{}': {}", path, message)
+      }
+      Db2MdError::Workbook(msg) => {
+        write!(f, "cannot read data: {}", msg)
+      }
+      Db2MdError::Schema(msg) => {
+        write!(f, "invalid schema: {}", msg)
+      }
+      Db2MdError::Mapping(msg) => {
+        write!(f, "cannot map fields: {}", msg)
+      }
+      Db2MdError::Render(msg) => {
+        write!(f, "cannot render row: {}", msg)
+      }
+      Db2MdError::Coerce { field, message } => {
+        write!(f, "cannot convert field {}: {}", field, message)
+      }
+      Db2MdError::ErrorCell { field, value } => {
+        write!(f, "field {} holds the error value {}", field, value)
+      }
+      Db2MdError::Expr { field, message } => {
+        write!(f, "cannot compute field {}: {}", field, message)
+      }
+      Db2MdError::Rule { field, message } => {
+        write!(f, "field {} {}", field, message)
+      }
+      Db2MdError::InvalidRow { row, message } => {
+        write!(f, "row {} is invalid: {}", row, message)
+      }
+    }
+  }
+}
+
+impl std::error::Error for Db2MdError {}
+
+// Reports carry errors as their message
+impl Serialize for Db2MdError
+{
+  fn serialize<S: Serializer>(&self,
+                              serializer: S)
+                              -> Result<S::Ok, S::Error>
+  {
+    serializer.collect_str(self)
+  }
+}
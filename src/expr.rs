@@ -0,0 +1,692 @@
+use chrono::{Datelike, Local};
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use std::fmt;
+
+use crate::cell::Cell;
+use crate::field_type::{to_datetime, to_number};
+use crate::yaml_parser::FieldSpec;
+
+// Length and nesting allowed in an expression, so that no schema can
+// overflow the stack while parsing or evaluating it
+const MAX_TOKENS: usize = 1024;
+const MAX_DEPTH: usize = 64;
+
+// Name, least and most arguments of every function, expressions can
+// call nothing else
+const FUNCTIONS: [(&str, usize, usize); 12] = [("if", 3, 3),
+                                               ("coalesce", 1, 64),
+                                               ("concat", 1, 64),
+                                               ("lookup", 2, 129),
+                                               ("round", 1, 2),
+                                               ("upper", 1, 1),
+                                               ("lower", 1, 1),
+                                               ("trim", 1, 1),
+                                               ("len", 1, 1),
+                                               ("days", 2, 2),
+                                               ("years", 2, 2),
+                                               ("today", 0, 0)];
+
+// Formula of a computed field, e.g.
+// `full_name: {type: text, expr: "first & ' ' & last"}`, evaluated
+// per row from the other fields. It reads cells and nothing else
+#[derive(Clone)]
+pub struct Expr
+{
+  source: String,
+  root: Node,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Node
+{
+  Literal(Cell),
+  // path of another field
+  Field(String),
+  Neg(Box<Node>),
+  Not(Box<Node>),
+  Binary(BinOp, Box<Node>, Box<Node>),
+  Call(String, Vec<Node>),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum BinOp
+{
+  Or,
+  And,
+  Eq,
+  Ne,
+  Lt,
+  Le,
+  Gt,
+  Ge,
+  Concat,
+  Add,
+  Sub,
+  Mul,
+  Div,
+  Rem,
+}
+
+impl Expr
+{
+  // The error tells what is wrong and where
+  pub fn parse(source: &str) -> Result<Self, String>
+  {
+    let tokens = tokenize(source)?;
+    if tokens.len() > MAX_TOKENS {
+      return Err(format!("expression longer than {} tokens",
+                         MAX_TOKENS));
+    }
+    let mut parser = Parser { tokens, pos: 0, depth: 0 };
+    let root = parser.expr()?;
+    if let Some(token) = parser.tokens.get(parser.pos) {
+      return Err(format!("unexpected {}", token));
+    }
+    Ok(Self { source: source.to_string(), root })
+  }
+
+  pub fn as_str(&self) -> &str
+  {
+    &self.source
+  }
+
+  // Paths of the fields it reads, in order of appearance
+  pub fn fields(&self) -> Vec<&str>
+  {
+    let mut fields = vec![];
+    collect_fields(&self.root, &mut fields);
+    fields
+  }
+
+  // `lookup` gives the cell of a field by its path, None for fields
+  // that do not exist
+  pub fn eval(&self,
+              lookup: &dyn Fn(&str) -> Option<Cell>)
+              -> Result<Cell, String>
+  {
+    eval(&self.root, lookup)
+  }
+}
+
+impl PartialEq for Expr
+{
+  fn eq(&self, other: &Self) -> bool
+  {
+    self.source == other.source
+  }
+}
+
+impl fmt::Debug for Expr
+{
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result
+  {
+    write!(f, "Expr({:?})", self.source)
+  }
+}
+
+// Cells of the fields of a row that expressions read, after fallbacks
+// and defaults, empty for fields without a column or default
+pub fn row_lookup<'a>(row: &'a [Cell],
+                      fields: &'a [&FieldSpec],
+                      field_map: &'a HashMap<String, usize>,
+                      fallbacks: &'a HashMap<String, Vec<usize>>)
+                      -> impl Fn(&str) -> Option<Cell> + 'a
+{
+  move |path| {
+    let field =
+      fields.iter().find(|f| f.path == path && f.expr.is_none())?;
+    let column = field_map.get(path).copied();
+    let fallback = fallbacks.get(path).map(Vec::as_slice);
+    let cell = field.pick(row, column, fallback.unwrap_or_default());
+    Some(cell.cloned().unwrap_or_default())
+  }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token
+{
+  Number(f64),
+  Text(String),
+  Name(String),
+  // `a name` between backticks, never a keyword or a function
+  Quoted(String),
+  // operators, parentheses and commas
+  Symbol(&'static str),
+}
+
+impl fmt::Display for Token
+{
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result
+  {
+    match self {
+      Token::Number(x) => write!(f, "number {}", x),
+      Token::Text(s) => write!(f, "text '{}'", s),
+      Token::Name(name) => write!(f, "'{}'", name),
+      Token::Quoted(name) => write!(f, "`{}`", name),
+      Token::Symbol(s) => write!(f, "'{}'", s),
+    }
+  }
+}
+
+const SYMBOLS: [&str; 16] = ["==", "!=", "<=", ">=", "<", ">", "&",
+                             "+", "-", "*", "/", "%", "(", ")",
+                             ",", "="];
+
+fn tokenize(source: &str) -> Result<Vec<Token>, String>
+{
+  let chars = source.chars().collect::<Vec<_>>();
+  let mut tokens = vec![];
+  let mut i = 0;
+  while i < chars.len() {
+    let c = chars[i];
+    if c.is_whitespace() {
+      i += 1;
+    } else if c.is_ascii_digit() {
+      let start = i;
+      while i < chars.len()
+            && (chars[i].is_ascii_digit() || chars[i] == '.')
+      {
+        i += 1;
+      }
+      let number = chars[start..i].iter().collect::<String>();
+      let x =
+        number.parse::<f64>()
+              .map_err(|_| format!("invalid number {}", number))?;
+      tokens.push(Token::Number(x));
+    } else if c == '\'' || c == '"' || c == '`' {
+      // `quoted names` hold spaces and dashes
+      let end =
+        chars[i + 1..].iter()
+                      .position(|&q| q == c)
+                      .ok_or_else(|| format!("unclosed {}", c))?;
+      let text =
+        chars[i + 1..i + 1 + end].iter().collect::<String>();
+      tokens.push(if c == '`' {
+                    Token::Quoted(text)
+                  } else {
+                    Token::Text(text)
+                  });
+      i += end + 2;
+    } else if c.is_alphabetic() || c == '_' {
+      let start = i;
+      while i < chars.len()
+            && (chars[i].is_alphanumeric()
+                || chars[i] == '_'
+                || chars[i] == '.')
+      {
+        i += 1;
+      }
+      tokens.push(Token::Name(chars[start..i].iter().collect()));
+    } else {
+      let rest = chars[i..].iter().take(2).collect::<String>();
+      let symbol =
+        SYMBOLS.iter()
+               .find(|s| rest.starts_with(*s))
+               .ok_or_else(|| format!("unexpected '{}'", c))?;
+      tokens.push(Token::Symbol(symbol));
+      i += symbol.len();
+    }
+  }
+  Ok(tokens)
+}
+
+// Recursive descent, from the loosest operator to the tightest:
+// or, and, not, comparisons, &, + -, * / %, unary -
+struct Parser
+{
+  tokens: Vec<Token>,
+  pos: usize,
+  depth: usize,
+}
+
+impl Parser
+{
+  fn peek(&self) -> Option<&Token>
+  {
+    self.tokens.get(self.pos)
+  }
+
+  fn eat_symbol(&mut self, symbol: &'static str) -> bool
+  {
+    if self.peek() == Some(&Token::Symbol(symbol)) {
+      self.pos += 1;
+      true
+    } else {
+      false
+    }
+  }
+
+  fn eat_keyword(&mut self, keyword: &str) -> bool
+  {
+    match self.peek() {
+      Some(Token::Name(name))
+        if name.eq_ignore_ascii_case(keyword) =>
+      {
+        self.pos += 1;
+        true
+      }
+      _ => false,
+    }
+  }
+
+  fn expect(&mut self, symbol: &'static str) -> Result<(), String>
+  {
+    if self.eat_symbol(symbol) {
+      return Ok(());
+    }
+    Err(self.missing(&format!("'{}'", symbol)))
+  }
+
+  fn missing(&self, expected: &str) -> String
+  {
+    match self.peek() {
+      Some(token) => format!("expected {} but found {}", expected, token),
+      None => format!("expected {} at the end", expected),
+    }
+  }
+
+  fn expr(&mut self) -> Result<Node, String>
+  {
+    self.nested(Self::or)
+  }
+
+  fn or(&mut self) -> Result<Node, String>
+  {
+    let mut node = self.and()?;
+    while self.eat_keyword("or") {
+      node = binary(BinOp::Or, node, self.and()?);
+    }
+    Ok(node)
+  }
+
+  fn and(&mut self) -> Result<Node, String>
+  {
+    let mut node = self.not()?;
+    while self.eat_keyword("and") {
+      node = binary(BinOp::And, node, self.not()?);
+    }
+    Ok(node)
+  }
+
+  fn not(&mut self) -> Result<Node, String>
+  {
+    if self.eat_keyword("not") {
+      let node = self.nested(Self::not)?;
+      return Ok(Node::Not(Box::new(node)));
+    }
+    self.comparison()
+  }
+
+  fn comparison(&mut self) -> Result<Node, String>
+  {
+    let node = self.concat()?;
+    let ops = [("==", BinOp::Eq),
+               ("=", BinOp::Eq),
+               ("!=", BinOp::Ne),
+               ("<=", BinOp::Le),
+               (">=", BinOp::Ge),
+               ("<", BinOp::Lt),
+               (">", BinOp::Gt)];
+    for (symbol, op) in ops {
+      if self.eat_symbol(symbol) {
+        return Ok(binary(op, node, self.concat()?));
+      }
+    }
+    Ok(node)
+  }
+
+  fn concat(&mut self) -> Result<Node, String>
+  {
+    let mut node = self.sum()?;
+    while self.eat_symbol("&") {
+      node = binary(BinOp::Concat, node, self.sum()?);
+    }
+    Ok(node)
+  }
+
+  fn sum(&mut self) -> Result<Node, String>
+  {
+    let mut node = self.product()?;
+    loop {
+      let op = if self.eat_symbol("+") {
+        BinOp::Add
+      } else if self.eat_symbol("-") {
+        BinOp::Sub
+      } else {
+        return Ok(node);
+      };
+      node = binary(op, node, self.product()?);
+    }
+  }
+
+  fn product(&mut self) -> Result<Node, String>
+  {
+    let mut node = self.unary()?;
+    loop {
+      let op = if self.eat_symbol("*") {
+        BinOp::Mul
+      } else if self.eat_symbol("/") {
+        BinOp::Div
+      } else if self.eat_symbol("%") {
+        BinOp::Rem
+      } else {
+        return Ok(node);
+      };
+      node = binary(op, node, self.unary()?);
+    }
+  }
+
+  fn unary(&mut self) -> Result<Node, String>
+  {
+    if self.eat_symbol("-") {
+      let node = self.nested(Self::unary)?;
+      return Ok(Node::Neg(Box::new(node)));
+    }
+    self.primary()
+  }
+
+  // Parentheses, arguments and unary operators nest
+  fn nested(&mut self,
+            parse: fn(&mut Self) -> Result<Node, String>)
+            -> Result<Node, String>
+  {
+    self.depth += 1;
+    if self.depth > MAX_DEPTH {
+      return Err(String::from("expression nested too deeply"));
+    }
+    let node = parse(self);
+    self.depth -= 1;
+    node
+  }
+
+  fn primary(&mut self) -> Result<Node, String>
+  {
+    let Some(token) = self.peek().cloned() else {
+      return Err(String::from("unexpected end"));
+    };
+    self.pos += 1;
+    match token {
+      Token::Number(x) => Ok(Node::Literal(number_cell(x))),
+      Token::Text(s) => Ok(Node::Literal(Cell::Text(s))),
+      Token::Symbol("(") => {
+        let node = self.expr()?;
+        self.expect(")")?;
+        Ok(node)
+      }
+      Token::Name(name) if self.eat_symbol("(") => self.call(name),
+      Token::Name(name) => match name.to_lowercase().as_str() {
+        "true" => Ok(Node::Literal(Cell::Bool(true))),
+        "false" => Ok(Node::Literal(Cell::Bool(false))),
+        _ => Ok(Node::Field(name)),
+      },
+      Token::Quoted(name) => Ok(Node::Field(name)),
+      token => Err(format!("unexpected {}", token)),
+    }
+  }
+
+  fn call(&mut self, name: String) -> Result<Node, String>
+  {
+    let name = name.to_lowercase();
+    let Some(&(_, least, most)) =
+      FUNCTIONS.iter().find(|(f, ..)| *f == name)
+    else {
+      return Err(format!("unknown function {}", name));
+    };
+    let mut args = vec![];
+    if !self.eat_symbol(")") {
+      loop {
+        args.push(self.expr()?);
+        if self.eat_symbol(")") {
+          break;
+        }
+        if !self.eat_symbol(",") {
+          return Err(self.missing("',' or ')'"));
+        }
+      }
+    }
+    if args.len() < least || args.len() > most {
+      return Err(format!("{} takes {} arguments, not {}",
+                         name,
+                         if least == most {
+                           least.to_string()
+                         } else {
+                           format!("{} to {}", least, most)
+                         },
+                         args.len()));
+    }
+    Ok(Node::Call(name, args))
+  }
+}
+
+fn binary(op: BinOp, left: Node, right: Node) -> Node
+{
+  Node::Binary(op, Box::new(left), Box::new(right))
+}
+
+fn collect_fields<'a>(node: &'a Node, fields: &mut Vec<&'a str>)
+{
+  match node {
+    Node::Literal(_) => {}
+    Node::Field(path) => fields.push(path),
+    Node::Neg(node) | Node::Not(node) => {
+      collect_fields(node, fields)
+    }
+    Node::Binary(_, left, right) => {
+      collect_fields(left, fields);
+      collect_fields(right, fields);
+    }
+    Node::Call(_, args) => {
+      for arg in args {
+        collect_fields(arg, fields);
+      }
+    }
+  }
+}
+
+fn eval(node: &Node,
+        lookup: &dyn Fn(&str) -> Option<Cell>)
+        -> Result<Cell, String>
+{
+  match node {
+    Node::Literal(cell) => Ok(cell.clone()),
+    Node::Field(path) => {
+      lookup(path).ok_or_else(|| format!("unknown field {}", path))
+    }
+    Node::Neg(node) => {
+      Ok(number_cell(-number(&eval(node, lookup)?)?))
+    }
+    Node::Not(node) => {
+      Ok(Cell::Bool(!truthy(&eval(node, lookup)?)?))
+    }
+    Node::Binary(BinOp::And, left, right) => {
+      let value = truthy(&eval(left, lookup)?)?
+                  && truthy(&eval(right, lookup)?)?;
+      Ok(Cell::Bool(value))
+    }
+    Node::Binary(BinOp::Or, left, right) => {
+      let value = truthy(&eval(left, lookup)?)?
+                  || truthy(&eval(right, lookup)?)?;
+      Ok(Cell::Bool(value))
+    }
+    Node::Binary(op, left, right) => {
+      binary_op(*op, &eval(left, lookup)?, &eval(right, lookup)?)
+    }
+    Node::Call(name, args) => call(name, args, lookup),
+  }
+}
+
+fn binary_op(op: BinOp,
+             left: &Cell,
+             right: &Cell)
+             -> Result<Cell, String>
+{
+  let ordering = || compare(left, right);
+  let value = match op {
+    BinOp::Eq => Cell::Bool(ordering()? == Ordering::Equal),
+    BinOp::Ne => Cell::Bool(ordering()? != Ordering::Equal),
+    BinOp::Lt => Cell::Bool(ordering()? == Ordering::Less),
+    BinOp::Le => Cell::Bool(ordering()? != Ordering::Greater),
+    BinOp::Gt => Cell::Bool(ordering()? == Ordering::Greater),
+    BinOp::Ge => Cell::Bool(ordering()? != Ordering::Less),
+    BinOp::Concat => Cell::Text(text(left)? + &text(right)?),
+    _ => {
+      let (x, y) = (number(left)?, number(right)?);
+      let value = match op {
+        BinOp::Add => x + y,
+        BinOp::Sub => x - y,
+        BinOp::Mul => x * y,
+        BinOp::Div | BinOp::Rem if y == 0.0 => {
+          return Err(String::from("division by zero"))
+        }
+        BinOp::Div => x / y,
+        _ => x % y,
+      };
+      number_cell(value)
+    }
+  };
+  Ok(value)
+}
+
+fn call(name: &str,
+        args: &[Node],
+        lookup: &dyn Fn(&str) -> Option<Cell>)
+        -> Result<Cell, String>
+{
+  // only the branch taken is evaluated
+  if name == "if" {
+    let branch =
+      if truthy(&eval(&args[0], lookup)?)? { 1 } else { 2 };
+    return eval(&args[branch], lookup);
+  }
+  let mut values = vec![];
+  for arg in args {
+    values.push(eval(arg, lookup)?);
+  }
+  let value = match name {
+    "coalesce" => values.into_iter()
+                        .find(|v| !v.is_empty() && !v.is_error())
+                        .unwrap_or_default(),
+    "concat" => {
+      let mut joined = String::new();
+      for value in values.iter() {
+        joined.push_str(&text(value)?);
+      }
+      Cell::Text(joined)
+    }
+    // lookup(code, "O", "Open", "C", "Closed", "Unknown")
+    "lookup" => {
+      let key = text(&values[0])?;
+      let pairs = values[1..].chunks(2);
+      let mut found = Cell::Empty;
+      for pair in pairs {
+        match pair {
+          [k, v] if text(k)?.trim() == key.trim() => {
+            found = v.clone();
+            break;
+          }
+          [default] => found = default.clone(),
+          _ => {}
+        }
+      }
+      found
+    }
+    "round" => {
+      let digits = match values.get(1) {
+        Some(n) => number(n)?.clamp(0.0, 15.0) as i32,
+        None => 0,
+      };
+      let scale = 10f64.powi(digits);
+      number_cell((number(&values[0])? * scale).round() / scale)
+    }
+    "upper" => Cell::Text(text(&values[0])?.to_uppercase()),
+    "lower" => Cell::Text(text(&values[0])?.to_lowercase()),
+    "trim" => Cell::Text(text(&values[0])?.trim().to_string()),
+    "len" => Cell::Int(text(&values[0])?.chars().count() as i64),
+    // days from the first date to the second
+    "days" => {
+      let (from, to) = (date(&values[0])?, date(&values[1])?);
+      Cell::Int((to - from).num_days())
+    }
+    // whole years, such as an age
+    "years" => {
+      let (from, to) = (date(&values[0])?, date(&values[1])?);
+      let before =
+        (to.month(), to.day()) < (from.month(), from.day());
+      Cell::Int((to.year() - from.year() - before as i32) as i64)
+    }
+    "today" => Cell::Date(Local::now().date_naive()),
+    _ => return Err(format!("unknown function {}", name)),
+  };
+  Ok(value)
+}
+
+// Whole numbers are kept as integers so that `2 * 3` reads `6`
+fn number_cell(x: f64) -> Cell
+{
+  if x.fract() == 0.0 && x.abs() < 9e15 {
+    Cell::Int(x as i64)
+  } else {
+    Cell::Float(x)
+  }
+}
+
+fn checked(cell: &Cell) -> Result<&Cell, String>
+{
+  match cell {
+    Cell::Error(value) => Err(format!("a field holds {}", value)),
+    _ => Ok(cell),
+  }
+}
+
+fn number(cell: &Cell) -> Result<f64, String>
+{
+  match checked(cell)? {
+    cell if cell.is_empty() => Err(String::from("an empty value is no \
+                                                 number, use coalesce")),
+    cell => to_number(cell).filter(|x| x.is_finite())
+                           .ok_or_else(|| format!("'{}' is no number", cell)),
+  }
+}
+
+fn text(cell: &Cell) -> Result<String, String>
+{
+  Ok(checked(cell)?.to_string())
+}
+
+fn date(cell: &Cell) -> Result<chrono::NaiveDate, String>
+{
+  to_datetime(checked(cell)?).map(|t| t.date())
+                             .ok_or_else(|| format!("'{}' is no date", cell))
+}
+
+fn truthy(cell: &Cell) -> Result<bool, String>
+{
+  let value = match checked(cell)? {
+    Cell::Bool(b) => *b,
+    Cell::Int(i) => *i != 0,
+    Cell::Float(x) => *x != 0.0,
+    cell => !cell.is_empty(),
+  };
+  Ok(value)
+}
+
+// Numbers compare as numbers, dates as dates, anything else as text
+fn compare(left: &Cell, right: &Cell) -> Result<Ordering, String>
+{
+  let (left, right) = (checked(left)?, checked(right)?);
+  if left.is_number() && right.is_number() {
+    let (x, y) = (number(left)?, number(right)?);
+    return Ok(x.partial_cmp(&y).unwrap_or(Ordering::Equal));
+  }
+  let is_date =
+    |c: &Cell| matches!(c, Cell::Date(_) | Cell::DateTime(_));
+  if is_date(left) || is_date(right) {
+    if let (Some(x), Some(y)) =
+      (to_datetime(left), to_datetime(right))
+    {
+      return Ok(x.cmp(&y));
+    }
+  }
+  Ok(left.to_string().cmp(&right.to_string()))
+}
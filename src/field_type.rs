@@ -0,0 +1,366 @@
+use calamine::{ExcelDateTime, ExcelDateTimeType};
+use chrono::{NaiveDate, NaiveDateTime, NaiveTime, TimeDelta};
+use std::fmt::Write;
+
+use crate::cell::{
+  format_duration, format_float, millis_duration, parse_iso_duration,
+  Cell,
+};
+
+pub const DATE_FORMAT: &str = "%Y-%m-%d";
+pub const DATETIME_FORMAT: &str = "%Y-%m-%d %H:%M:%S";
+pub const TIME_FORMAT: &str = "%H:%M:%S";
+
+// Type a schema field declares, e.g. `price: number`
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum FieldType
+{
+  // anything not recognised is rendered as is
+  #[default]
+  Text,
+  Number,
+  Date,
+  DateTime,
+  Time,
+  Duration,
+  Bool,
+}
+
+impl FieldType
+{
+  pub fn parse(s: &str) -> Self
+  {
+    Self::from_name(s).unwrap_or_default()
+  }
+
+  // None for names that are no type, which parse takes as text
+  pub fn from_name(s: &str) -> Option<Self>
+  {
+    let kind = match s.trim().to_lowercase().as_str() {
+      "text" | "string" => FieldType::Text,
+      "number" | "numeric" | "int" | "integer" | "float" => {
+        FieldType::Number
+      }
+      "date" => FieldType::Date,
+      "datetime" | "timestamp" => FieldType::DateTime,
+      "time" => FieldType::Time,
+      "duration" => FieldType::Duration,
+      "bool" | "boolean" => FieldType::Bool,
+      _ => return None,
+    };
+    Some(kind)
+  }
+
+  // Name as written in a schema
+  pub fn name(&self) -> &'static str
+  {
+    match self {
+      FieldType::Text => "text",
+      FieldType::Number => "number",
+      FieldType::Date => "date",
+      FieldType::DateTime => "datetime",
+      FieldType::Time => "time",
+      FieldType::Duration => "duration",
+      FieldType::Bool => "bool",
+    }
+  }
+}
+
+// What to write for an empty or error cell
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub enum EmptyPolicy
+{
+  // an empty section
+  #[default]
+  Blank,
+  // leave out the section
+  Skip,
+  Placeholder(String),
+  // fail the whole row
+  Fail,
+}
+
+impl EmptyPolicy
+{
+  // `skip`, `fail` or `blank`, any other text is a placeholder
+  pub fn parse(s: &str) -> Self
+  {
+    match s.trim().to_lowercase().as_str() {
+      "blank" => EmptyPolicy::Blank,
+      "skip" => EmptyPolicy::Skip,
+      "fail" => EmptyPolicy::Fail,
+      _ => EmptyPolicy::Placeholder(s.to_string()),
+    }
+  }
+}
+
+// Declared type of a field with the options to render it, set per
+// field or for the whole schema under `_options`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FieldFormat
+{
+  pub kind: FieldType,
+  // digits after the decimal point of numbers, as read if unset
+  pub precision: Option<usize>,
+  // separator between groups of thousands of numbers
+  pub thousands: Option<String>,
+  // strftime format of this field, whatever the kind of its cells
+  pub format: Option<String>,
+  // strftime formats of date, datetime and time cells otherwise
+  pub date_format: String,
+  pub datetime_format: String,
+  pub time_format: String,
+  pub empty: EmptyPolicy,
+}
+
+impl Default for FieldFormat
+{
+  fn default() -> Self
+  {
+    Self { kind: FieldType::Text,
+           precision: None,
+           thousands: None,
+           format: None,
+           date_format: DATE_FORMAT.to_string(),
+           datetime_format: DATETIME_FORMAT.to_string(),
+           time_format: TIME_FORMAT.to_string(),
+           empty: EmptyPolicy::Blank }
+  }
+}
+
+impl FieldFormat
+{
+  // Render a cell as the declared type, the error tells why the value
+  // does not fit
+  pub fn render(&self, cell: &Cell) -> Result<String, String>
+  {
+    if cell.is_empty() {
+      return Ok(String::new());
+    }
+    match self.kind {
+      FieldType::Text => match cell {
+        Cell::Date(d) => {
+          let fmt = self.pick(&self.date_format);
+          strftime(d.format(fmt), fmt)
+        }
+        Cell::DateTime(t) => {
+          let fmt = self.pick(&self.datetime_format);
+          strftime(t.format(fmt), fmt)
+        }
+        Cell::Time(t) => {
+          let fmt = self.pick(&self.time_format);
+          strftime(t.format(fmt), fmt)
+        }
+        _ => Ok(cell.to_string()),
+      },
+      FieldType::Number => {
+        self.format_number(cell)
+            .ok_or_else(|| not_a(cell, "number"))
+      }
+      FieldType::Date => {
+        let date =
+          to_datetime(cell).map(|t| t.date())
+                           .ok_or_else(|| not_a(cell, "date"))?;
+        let fmt = self.pick(&self.date_format);
+        strftime(date.format(fmt), fmt)
+      }
+      FieldType::DateTime => {
+        let datetime =
+          to_datetime(cell).ok_or_else(|| not_a(cell, "datetime"))?;
+        let fmt = self.pick(&self.datetime_format);
+        strftime(datetime.format(fmt), fmt)
+      }
+      FieldType::Time => {
+        let time = to_time(cell).ok_or_else(|| not_a(cell, "time"))?;
+        let fmt = self.pick(&self.time_format);
+        strftime(time.format(fmt), fmt)
+      }
+      FieldType::Duration => {
+        to_duration(cell).map(format_duration)
+                         .ok_or_else(|| not_a(cell, "duration"))
+      }
+      FieldType::Bool => to_bool(cell).map(|b| {
+                           if b { "Yes" } else { "No" }.to_string()
+                         })
+                         .ok_or_else(|| not_a(cell, "boolean")),
+    }
+  }
+
+  // The field's own format wins over the one of its kind
+  fn pick<'a>(&'a self, default: &'a str) -> &'a str
+  {
+    self.format.as_deref().unwrap_or(default)
+  }
+
+  fn format_number(&self, cell: &Cell) -> Option<String>
+  {
+    // integers keep every digit, an f64 holds only 53 bits of them
+    let s = match (to_integer(cell), self.precision) {
+      (Some(i), None) => i.to_string(),
+      (_, Some(precision)) => {
+        format!("{:.*}", precision, to_number(cell)?)
+      }
+      (None, None) => format_float(to_number(cell)?),
+    };
+    match self.thousands.as_ref() {
+      Some(sep) => Some(group_thousands(&s, sep)),
+      None => Some(s),
+    }
+  }
+}
+
+// A bad format is reported instead of panicking
+fn strftime(formatted: impl std::fmt::Display,
+            fmt: &str)
+            -> Result<String, String>
+{
+  let mut s = String::new();
+  write!(s, "{}", formatted).map_err(|_| {
+                              format!("invalid format '{}'", fmt)
+                            })?;
+  Ok(s)
+}
+
+fn not_a(cell: &Cell, kind: &str) -> String
+{
+  format!("'{}' is not a {}", cell, kind)
+}
+
+pub fn to_number(cell: &Cell) -> Option<f64>
+{
+  match cell {
+    Cell::Int(i) => Some(*i as f64),
+    Cell::Float(x) => Some(*x),
+    Cell::Text(s) => s.trim().parse::<f64>().ok(),
+    _ => None,
+  }
+}
+
+fn to_integer(cell: &Cell) -> Option<i64>
+{
+  match cell {
+    Cell::Int(i) => Some(*i),
+    Cell::Text(s) => s.trim().parse::<i64>().ok(),
+    _ => None,
+  }
+}
+
+// Text in a few common layouts, or a spreadsheet serial number
+pub fn to_datetime(cell: &Cell) -> Option<NaiveDateTime>
+{
+  match cell {
+    Cell::Date(d) => Some(d.and_time(NaiveTime::MIN)),
+    Cell::DateTime(t) => Some(*t),
+    Cell::Int(_) | Cell::Float(_) => {
+      let serial = to_number(cell)?;
+      ExcelDateTime::new(serial, ExcelDateTimeType::DateTime, false)
+        .as_datetime()
+    }
+    Cell::Text(s) => {
+      let s = s.trim();
+      ["%Y-%m-%d %H:%M:%S", "%Y-%m-%d %H:%M", "%Y/%m/%d %H:%M:%S",
+       "%Y/%m/%d %H:%M"]
+        .iter()
+        .find_map(|fmt| NaiveDateTime::parse_from_str(s, fmt).ok())
+        .or_else(|| s.parse::<NaiveDateTime>().ok())
+        .or_else(|| {
+          ["%Y-%m-%d", "%Y/%m/%d", "%Y.%m.%d", "%d.%m.%Y"]
+            .iter()
+            .find_map(|fmt| NaiveDate::parse_from_str(s, fmt).ok())
+            .map(|d| d.and_time(NaiveTime::MIN))
+        })
+    }
+    _ => None,
+  }
+}
+
+fn to_time(cell: &Cell) -> Option<NaiveTime>
+{
+  match cell {
+    Cell::Time(t) => Some(*t),
+    Cell::DateTime(t) => Some(t.time()),
+    Cell::Float(_) => to_datetime(cell).map(|t| t.time()),
+    Cell::Text(s) => {
+      let s = s.trim();
+      ["%H:%M:%S", "%H:%M"].iter()
+                           .find_map(|fmt| {
+                             NaiveTime::parse_from_str(s, fmt).ok()
+                           })
+                           .or_else(|| {
+                             to_datetime(cell).map(|t| t.time())
+                           })
+    }
+    _ => None,
+  }
+}
+
+// Spreadsheet numbers count days, text is `h:mm:ss` or ISO 8601
+fn to_duration(cell: &Cell) -> Option<TimeDelta>
+{
+  match cell {
+    Cell::Duration(d) => Some(*d),
+    Cell::Time(t) => Some(*t - NaiveTime::MIN),
+    Cell::Int(_) | Cell::Float(_) => {
+      millis_duration(to_number(cell)? * 86_400_000.0)
+    }
+    Cell::Text(s) => {
+      let s = s.trim();
+      parse_iso_duration(s).or_else(|| parse_clock_duration(s))
+    }
+    _ => None,
+  }
+}
+
+fn to_bool(cell: &Cell) -> Option<bool>
+{
+  match cell {
+    Cell::Bool(b) => Some(*b),
+    Cell::Int(0) => Some(false),
+    Cell::Int(1) => Some(true),
+    Cell::Float(x) if *x == 0.0 || *x == 1.0 => Some(*x == 1.0),
+    Cell::Text(s) => match s.trim().to_lowercase().as_str() {
+      "true" | "yes" | "y" | "1" => Some(true),
+      "false" | "no" | "n" | "0" => Some(false),
+      _ => None,
+    },
+    _ => None,
+  }
+}
+
+// 1234567.5 -> 1,234,567.5
+fn group_thousands(s: &str, sep: &str) -> String
+{
+  let (sign, unsigned) = match s.strip_prefix('-') {
+    Some(rest) => ("-", rest),
+    None => ("", s),
+  };
+  let (int_part, frac_part) = match unsigned.find('.') {
+    Some(idx) => unsigned.split_at(idx),
+    None => (unsigned, ""),
+  };
+  let mut grouped = String::new();
+  for (idx, c) in int_part.chars().enumerate() {
+    if idx > 0 && (int_part.len() - idx) % 3 == 0 {
+      grouped.push_str(sep);
+    }
+    grouped.push(c);
+  }
+  format!("{}{}{}", sign, grouped, frac_part)
+}
+
+// `h:mm` or `h:mm:ss`, hours may exceed a day
+fn parse_clock_duration(s: &str) -> Option<TimeDelta>
+{
+  let parts = s.split(':')
+               .map(|p| p.parse::<i64>().ok())
+               .collect::<Option<Vec<_>>>()?;
+  let (h, m, sec) = match parts[..] {
+    [h, m] => (h, m, 0),
+    [h, m, sec] => (h, m, sec),
+    _ => return None,
+  };
+  let secs = h.checked_mul(3600)?
+              .checked_add(m.checked_mul(60)?)?
+              .checked_add(sec)?;
+  TimeDelta::try_seconds(secs)
+}
@@ -1,23 +1,39 @@
 use iced::advanced::widget::text as advanced_text;
+use iced::futures::channel::oneshot;
 use iced::widget::{
   button, checkbox,
   checkbox::Icon,
   column, container, image,
   image::Handle,
-  progress_bar, row, text,
+  pick_list, progress_bar, row, text,
   text::{LineHeight, Shaping},
   text_input, Space,
 };
 use iced::{
-  advanced::image::Bytes, alignment::Vertical, Color, Element, Fill,
-  Font, Length, Task,
+  advanced::image::Bytes, alignment::Vertical, time, Color, Element,
+  Fill, Font, Length, Subscription, Task,
 };
 use rfd::AsyncFileDialog;
 
-use crate::reader::read_excel;
-use crate::write_row_to_md;
+use crate::reader::{
+  delimited, detect_header, has_column_names, list_sheets,
+  read_data, HeaderLayout, ReadOptions, SheetSelector,
+};
+use crate::schema_gen::{
+  infer_schema, schema_to_string, SAMPLE_ROWS,
+};
+use crate::schema_lint::{
+  lint_schema_file, unmapped_columns, LintIssue,
+};
 use crate::yaml_parser::*;
+use crate::{
+  default_jobs, process_data, process_sheets, Cell,
+  ConversionReport, ConvertOptions, Db2MdError,
+};
 use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
 
 // State management
 #[derive(Debug)]
@@ -25,21 +41,37 @@ pub struct Db2MdApp
 {
   // data file
   has_header: bool,
+  // header layout as typed, parsed when used
+  header_row: String,
+  header_rows: String,
+  skip_rows: String,
   selected_file: Option<String>,
   sheet_name: Option<String>,
+  sheet_names: Vec<String>,
+  all_sheets: bool,
   rows_loaded: Option<usize>,
   cols_loaded: Option<usize>,
-  data_matrix: Vec<Vec<String>>,
+  data_matrix: Vec<Vec<Cell>>,
   // schema yaml
   selected_yaml: Option<String>,
   fields_map: HashMap<String, usize>,
   invalid_fields: Vec<String>,
+  // closest headers of each invalid field
+  suggestions: Vec<(String, Vec<String>)>,
+  // nest columns sharing a prefix in a generated schema
+  group_schema: bool,
+  schema_error: Option<Db2MdError>,
+  schema_issues: Vec<LintIssue>,
   // I/O
   file_prefix: String,
   output_dir: String,
-  progress: usize,
-  write_fails: Vec<usize>,
+  jobs: String,
+  progress: Arc<Mutex<f32>>,
+  load_error: Option<Db2MdError>,
+  reports: Vec<ConversionReport>,
+  convert_error: Option<Db2MdError>,
   is_loading: bool,
+  is_converting: bool,
 }
 
 // Messages for user interactions and async operations
@@ -49,14 +81,24 @@ pub enum Message
   SelectFile,
   FileSelected(Option<String>),
   LoadFile,
+  SelectSheet(String),
   SelectYaml,
   YamlSelected(Option<String>),
   LoadYaml,
+  InitSchema,
+  SchemaPathSelected(Option<String>),
+  SetGroupSchema(bool),
   SetHasHeader(bool),
+  SetHeaderRow(String),
+  SetHeaderRows(String),
+  SetSkipRows(String),
+  SetAllSheets(bool),
   SetFilePrefix(String),
   SetOutputDir(String),
+  SetJobs(String),
   Convert,
-  UpdateProgress(usize),
+  Converted(Result<Vec<ConversionReport>, Db2MdError>),
+  Tick,
   RowsLoaded,
 }
 
@@ -65,24 +107,50 @@ impl Default for Db2MdApp
   fn default() -> Self
   {
     Self { has_header: false,
+           header_row: String::from("0"),
+           header_rows: String::from("1"),
+           skip_rows: String::from("0"),
            selected_file: None,
            selected_yaml: None,
            file_prefix: String::from("ccms-doc"),
            output_dir: String::from("_md"),
-           progress: 0usize,
+           jobs: default_jobs().to_string(),
+           progress: Arc::new(Mutex::new(0f32)),
            sheet_name: None,
+           sheet_names: Vec::new(),
+           all_sheets: false,
            rows_loaded: None,
            cols_loaded: None,
            data_matrix: Vec::new(),
-           write_fails: Vec::new(),
+           load_error: None,
+           reports: Vec::new(),
            fields_map: HashMap::new(),
            invalid_fields: Vec::new(),
-           is_loading: false }
+           suggestions: Vec::new(),
+           group_schema: false,
+           schema_error: None,
+           schema_issues: Vec::new(),
+           convert_error: None,
+           is_loading: false,
+           is_converting: false }
   }
 }
 
 impl Db2MdApp
 {
+  // Fields left empty or invalid keep their default
+  fn header_layout(&self) -> HeaderLayout
+  {
+    let default = HeaderLayout::default();
+    let parse = |value: &str, default: usize| {
+      value.trim().parse().unwrap_or(default)
+    };
+    HeaderLayout { row: parse(&self.header_row, default.row),
+                   rows:
+                     parse(&self.header_rows, default.rows).max(1),
+                   skip: parse(&self.skip_rows, default.skip) }
+  }
+
   pub fn title(&self) -> String
   {
     String::from("db2md")
@@ -101,8 +169,20 @@ impl Db2MdApp
       Message::SelectFile => {
         // Launch file dialog
         Task::perform(async {
-                        AsyncFileDialog::new().add_filter("Excel",
-                                                          &["xlsx"])
+                        AsyncFileDialog::new().add_filter("Data",
+                                                          &["xlsx",
+                                                            "xlsm",
+                                                            "xls",
+                                                            "xlsb",
+                                                            "ods",
+                                                            "csv",
+                                                            "tsv",
+                                                            "db",
+                                                            "sqlite",
+                                                            "sqlite3",
+                                                            "json",
+                                                            "ndjson",
+                                                            "jsonl"])
                                               .pick_file()
                                               .await
                                               .map(|file| {
@@ -120,25 +200,55 @@ impl Db2MdApp
       }
 
       Message::LoadFile => {
-        if self.selected_file.is_none() {
-          return Task::none();
-        } else {
+        if let Some(file) = self.selected_file.as_ref() {
           self.is_loading = true;
+          // keep the picked sheet if the workbook has it
+          self.sheet_names = list_sheets(file).unwrap_or_default();
+          let selector = match self.sheet_name.as_ref() {
+            Some(name) if self.sheet_names.contains(name) => {
+              SheetSelector::Name(name.clone())
+            }
+            _ => SheetSelector::default(),
+          };
+          let options = ReadOptions { sheet: selector,
+                                      ..ReadOptions::default() };
           // loading
           let mut data = vec![];
-          let meta =
-            read_excel(self.selected_file.as_ref().unwrap(),
-                       &mut data).unwrap_or((String::from("N/A"),
-                                             0,
-                                             0));
+          let meta = match read_data(file, &options, &mut data) {
+            Ok(meta) => meta,
+            Err(e) => {
+              // nothing is loaded, the status line shows why
+              self.load_error = Some(e);
+              self.data_matrix.clear();
+              self.rows_loaded = None;
+              self.cols_loaded = None;
+              self.sheet_name = None;
+              return Task::perform(async {}, |_| Message::RowsLoaded);
+            }
+          };
+          self.load_error = None;
+          // csv files carry no hint, guess whether there is a header
+          if has_column_names(file) {
+            self.has_header = true;
+          } else if delimited::is_delimited(file) {
+            let from = self.header_layout().row.min(data.len());
+            self.has_header = detect_header(&data[from..]);
+          }
           self.data_matrix = data;
           self.rows_loaded = Some(meta.1);
           self.cols_loaded = Some(meta.2);
           self.sheet_name = Some(meta.0);
           Task::perform(async {}, |_| Message::RowsLoaded)
+        } else {
+          Task::none()
         }
       }
 
+      Message::SelectSheet(name) => {
+        self.sheet_name = Some(name);
+        Task::done(Message::LoadFile)
+      }
+
       Message::SelectYaml => {
         // Launch file dialog
         Task::perform(async {
@@ -161,29 +271,100 @@ impl Db2MdApp
       }
 
       Message::LoadYaml => {
-        if self.selected_yaml.is_none() {
-          return Task::none();
-        } else {
+        self.schema_error = None;
+        if let Some(yaml) = self.selected_yaml.as_ref() {
+          // mistakes in the schema, with their line and column
+          self.schema_issues = match lint_schema_file(yaml) {
+            Ok(issues) => issues,
+            Err(e) => {
+              self.schema_error = Some(e);
+              vec![]
+            }
+          };
+          self.fields_map.clear();
+          self.invalid_fields.clear();
           // loading
-          if let Ok(yml) =
-            parse_yaml_schema(self.selected_yaml.as_ref().unwrap())
-          {
-            let mut fields_map_raw = vec![];
-            extract_fields(&yml, "", &mut fields_map_raw);
-
+          if let Ok(schema) = Schema::load(yaml) {
             let headers = if self.has_header {
-              self.data_matrix.first().unwrap()
+              self.header_layout().headers(&self.data_matrix)
             } else {
-              &Vec::new()
+              Vec::new()
             };
-            self.invalid_fields.clear();
             self.fields_map =
-              map_fields_to_columns(fields_map_raw.as_ref(),
-                                    headers,
+              map_fields_to_columns(&schema.fields(),
+                                    &headers,
                                     &mut self.invalid_fields);
+            let fallbacks =
+              map_fallback_columns(&schema.fields(), &headers);
+            self.suggestions =
+              self.invalid_fields
+                  .iter()
+                  .map(|f| (f.clone(), suggest_headers(f, &headers)))
+                  .filter(|(_, s)| !s.is_empty())
+                  .collect();
+            if let Some(cols) = self.cols_loaded {
+              self.schema_issues
+                  .extend(unmapped_columns(&self.fields_map,
+                                           &fallbacks,
+                                           &headers,
+                                           cols));
+            }
           }
+        }
+        Task::none()
+      }
+
+      Message::InitSchema => {
+        if self.data_matrix.is_empty() {
           return Task::none();
         }
+        Task::perform(async {
+                        AsyncFileDialog::new().add_filter("Yaml",
+                                                          &["yaml"])
+                                              .set_file_name("schema.yaml")
+                                              .save_file()
+                                              .await
+                                              .map(|file| {
+                                                file.path()
+                                                    .to_string_lossy()
+                                                    .into_owned()
+                                              })
+                      },
+                      Message::SchemaPathSelected)
+      }
+
+      Message::SchemaPathSelected(path) => {
+        let Some(path) = path else {
+          return Task::none();
+        };
+        // the header is where the layout inputs say
+        let res = infer_schema(&self.data_matrix,
+                               &self.header_layout(),
+                               SAMPLE_ROWS,
+                               self.group_schema)
+                  .and_then(|schema| schema_to_string(&schema))
+                  .and_then(|yaml| {
+                    std::fs::write(&path, yaml).map_err(|e| {
+                      Db2MdError::io(&path, e)
+                    })
+                  });
+        match res {
+          Ok(()) => {
+            self.schema_error = None;
+            self.has_header = true;
+            self.selected_yaml = Some(path);
+            Task::done(Message::LoadYaml)
+          }
+          Err(e) => {
+            self.schema_error = Some(e);
+            Task::none()
+          }
+        }
+      }
+
+      Message::SetGroupSchema(value) => {
+        self.group_schema = value;
+        Task::none()
       }
 
       Message::SetHasHeader(value) => {
@@ -191,43 +372,120 @@ impl Db2MdApp
         Task::none()
       }
 
+      Message::SetAllSheets(value) => {
+        self.all_sheets = value;
+        Task::none()
+      }
+
       Message::SetFilePrefix(value) => {
         self.file_prefix = value;
         Task::none()
       }
 
       Message::SetOutputDir(value) => {
-        self.file_prefix = value;
+        self.output_dir = value;
         Task::none()
       }
 
-      Message::Convert => {
-        self.write_fails.clear();
-        Task::perform(async { 1usize }, Message::UpdateProgress)
+      Message::SetJobs(value) => {
+        self.jobs = value;
+        Task::none()
       }
 
-      Message::UpdateProgress(value) => {
-        if value == 0usize {
-          self.write_fails.push(self.progress + 1usize);
-        }
-        let row = self.data_matrix.get(self.progress);
-        self.progress += 1usize;
-        if row.is_none() {
+      Message::SetHeaderRow(value) => {
+        self.header_row = value;
+        Task::none()
+      }
+
+      Message::SetHeaderRows(value) => {
+        self.header_rows = value;
+        Task::none()
+      }
+
+      Message::SetSkipRows(value) => {
+        self.skip_rows = value;
+        Task::none()
+      }
+
+      Message::Convert => {
+        let (Some(file), Some(yaml)) =
+          (self.selected_file.clone(), self.selected_yaml.clone())
+        else {
           return Task::none();
+        };
+        if self.is_converting {
+          return Task::none();
+        }
+        self.reports.clear();
+        self.convert_error = None;
+        self.is_converting = true;
+        *self.progress.lock().unwrap() = 0f32;
+
+        // convert in a worker thread and poll progress with Tick
+        let (sender, receiver) = oneshot::channel();
+        // fall back to one worker per core
+        let jobs = self.jobs
+                       .trim()
+                       .parse()
+                       .ok()
+                       .filter(|&n| n > 0)
+                       .unwrap_or_else(default_jobs);
+        let sheet = self.sheet_name
+                        .clone()
+                        .map(SheetSelector::Name)
+                        .unwrap_or_default();
+        let options =
+          ConvertOptions { md_prefix: self.file_prefix.clone(),
+                           output_dir: self.output_dir.clone(),
+                           has_header: self.has_header,
+                           detect_header: false,
+                           header: self.header_layout(),
+                           read:
+                             ReadOptions { sheet,
+                                           ..ReadOptions::default() },
+                           jobs };
+        // every sheet goes to its own subdirectory with the same schema
+        let sheet_schemas = if self.all_sheets {
+          self.sheet_names
+              .iter()
+              .map(|sheet| (sheet.clone(), yaml.clone()))
+              .collect()
         } else {
-          let row_data = row.unwrap();
-          let map = &self.fields_map;
-          let progress =
-            self.progress
-            + if self.has_header { 1usize } else { 0usize };
-          let prefix = &self.file_prefix;
-          let output_dir = &self.output_dir;
-          let res = write_row_to_md(row_data, map, progress,
-                                    output_dir, prefix);
-          Task::perform(async move { res }, Message::UpdateProgress)
+          vec![]
+        };
+        let progress = Arc::clone(&self.progress);
+        thread::spawn(move || {
+          let res = if sheet_schemas.is_empty() {
+            process_data(&file, &yaml, &options, &progress).map(|r| vec![r])
+          } else {
+            process_sheets(&file,
+                           &sheet_schemas,
+                           &options,
+                           &progress)
+          };
+          let _ = sender.send(res);
+        });
+        Task::perform(async move {
+                        receiver.await.unwrap_or_else(|_| {
+                          Err(Db2MdError::Render(String::from("conversion \
+                                                               aborted")))
+                        })
+                      },
+                      Message::Converted)
+      }
+
+      Message::Converted(res) => {
+        self.is_converting = false;
+        match res {
+          Ok(reports) => self.reports = reports,
+          Err(e) => self.convert_error = Some(e),
         }
+        Task::none()
       }
 
+      // redraw only, the progress bar reads the shared progress
+      Message::Tick => Task::none(),
+
       Message::RowsLoaded => {
         self.is_loading = false;
         Task::none()
@@ -235,7 +493,16 @@ impl Db2MdApp
     }
   }
 
-  pub fn view(&self) -> Element<Message>
+  pub fn subscription(&self) -> Subscription<Message>
+  {
+    if self.is_converting {
+      time::every(Duration::from_millis(100)).map(|_| Message::Tick)
+    } else {
+      Subscription::none()
+    }
+  }
+
+  pub fn view(&self) -> Element<'_, Message>
   {
     let png = include_bytes!(".././assets/header.png");
     let png_bytes = Bytes::from_static(png);
@@ -245,19 +512,29 @@ impl Db2MdApp
     let warn_color = Color::from_rgb(1.0, 0.6, 0.2);
 
     let path_text = if let Some(path) = self.selected_file.clone() {
-      text(format!("{}", path))
+      text(path)
     } else {
       text("Nothing selected")
     };
 
     let file_selection =
-      row![button("Select XLSX").on_press(Message::SelectFile),
+      row![button("Select File").on_press(Message::SelectFile),
            Space::with_width(10),
            path_text,
            Space::with_width(Length::Fill),
            button("Load").on_press(Message::LoadFile)].align_y(Vertical::Center).width(Fill);
 
-    let rows_info = if let Some(rows) = self.rows_loaded {
+    let sheet_selection =
+      row![text("Sheet"),
+           pick_list(self.sheet_names.as_slice(),
+                     self.sheet_name.as_ref(),
+                     Message::SelectSheet)
+             .placeholder("Load a file to pick a sheet")].spacing(10)
+                                                         .align_y(Vertical::Center);
+
+    let rows_info = if let Some(e) = self.load_error.as_ref() {
+      text(format!("Fail to load: {}", e)).color(warn_color)
+    } else if let Some(rows) = self.rows_loaded {
       let cols = self.cols_loaded.as_ref().unwrap();
       let sheet = self.sheet_name.as_ref().unwrap();
       text(format!("Loaded {} rows of {} strings in {}",
@@ -272,7 +549,7 @@ impl Db2MdApp
     };
 
     let yaml_path = if let Some(path) = self.selected_yaml.clone() {
-      text(format!("{}", path))
+      text(path)
     } else {
       text("No schema selected")
     };
@@ -284,8 +561,17 @@ impl Db2MdApp
            Space::with_width(Length::Fill),
            button("Load").on_press(Message::LoadYaml)].align_y(Vertical::Center).width(Fill);
 
-    let yaml_info = if self.fields_map.len() > 0 {
-      let cols = self.cols_loaded.as_ref().unwrap();
+    let schema_generation =
+      row![button("Generate YAML from header").on_press(Message::InitSchema),
+           checkbox("Group columns sharing a prefix", self.group_schema)
+             .on_toggle(Message::SetGroupSchema)].spacing(10)
+                                                 .align_y(Vertical::Center);
+
+    let yaml_info = if let Some(e) = self.schema_error.as_ref() {
+      text(format!("Schema error: {}", e)).color(warn_color)
+    } else if !self.fields_map.is_empty() {
+      // a schema may be loaded before any sheet
+      let cols = &self.cols_loaded.unwrap_or_default();
       let field_num = self.fields_map.len();
       if field_num > *cols {
         text(format!("Find {} fields but each row has {} columns, \
@@ -303,15 +589,31 @@ impl Db2MdApp
       text("No Field Loaded")
     };
 
-    let invalid_field = if self.invalid_fields.len() > 0 {
-      text(format!("Invalid fields in Yaml {:?}",
-                   self.invalid_fields)).color(warn_color)
-    } else if self.fields_map.len() > 0 {
+    let invalid_field = if !self.invalid_fields.is_empty() {
+      let mut message =
+        format!("Invalid fields in Yaml {:?}", self.invalid_fields);
+      for (field, headers) in self.suggestions.iter() {
+        message.push_str(&format!("\n{}: did you mean {}?",
+                                  field,
+                                  headers.join(", ")));
+      }
+      text(message).color(warn_color)
+    } else if !self.fields_map.is_empty() {
       text("All fields in Yaml are found in the sheet")
     } else {
       text("")
     };
 
+    let schema_lint = if self.schema_issues.is_empty() {
+      text("")
+    } else {
+      let lines = self.schema_issues
+                      .iter()
+                      .map(|issue| issue.to_string())
+                      .collect::<Vec<_>>();
+      text(lines.join("\n")).color(warn_color)
+    };
+
     let header_selection = row![text("Has header?"),
            checkbox("Yes", self.has_header).on_toggle(|_| {
                                    Message::SetHasHeader(true)
@@ -333,6 +635,27 @@ impl Db2MdApp
                 })].spacing(10)
                            .align_y(Vertical::Center);
 
+    let header_layout = row![
+            text("Header row"),
+            text_input("0", &self.header_row)
+                .on_input(Message::SetHeaderRow)
+                .padding(10),
+            text("Header rows"),
+            text_input("1", &self.header_rows)
+                .on_input(Message::SetHeaderRows)
+                .padding(10),
+            text("Skip rows"),
+            text_input("0", &self.skip_rows)
+                .on_input(Message::SetSkipRows)
+                .padding(10)
+        ].spacing(10)
+                        .align_y(Vertical::Center);
+
+    let sheets_selection = row![text("Convert all sheets?"),
+           checkbox("Yes, one subdirectory per sheet", self.all_sheets)
+             .on_toggle(Message::SetAllSheets)].spacing(10)
+                                              .align_y(Vertical::Center);
+
     let prefix_input = row![
             text("Prefix for generated files"),
             text_input("Text input", &self.file_prefix)
@@ -349,29 +672,77 @@ impl Db2MdApp
         ].spacing(10)
                      .align_y(Vertical::Center);
 
-    let percentage: f32 = self.progress as f32
-                          / self.rows_loaded.unwrap_or(1usize) as f32
-                          * 100f32;
+    let jobs_input = row![
+            text("Worker threads for conversion"),
+            text_input("Number of workers", &self.jobs)
+                .on_input(Message::SetJobs)
+                .padding(10)
+        ].spacing(10)
+                     .align_y(Vertical::Center);
+
+    let percentage: f32 = *self.progress.lock().unwrap();
     let progress =
       row![progress_bar(0.0..=100.0, percentage),
            button("Convert").on_press(Message::Convert)].spacing(10).align_y(Vertical::Center);
 
-    let completion_msg = if self.write_fails.len() > 0 {
-      text(format!("Fail to write rows: {:?}",
-    self.write_fails)).color(warn_color)
+    let completion_msg = if let Some(e) = self.convert_error.as_ref()
+    {
+      text(format!("Fail to convert: {}", e)).color(warn_color)
+    } else if !self.reports.is_empty() {
+      // one line per sheet, followed by one line per failed row
+      // and per warning
+      let mut lines = vec![];
+      let mut has_failures = false;
+      for report in self.reports.iter() {
+        lines.push(format!("Wrote {} of {} rows of {} into {}",
+                           report.written(),
+                           report.rows.len(),
+                           report.sheet,
+                           report.output_dir));
+        if report.skipped() > 0 {
+          lines.push(format!("Skipped {} rows breaking the rules of {}",
+                             report.skipped(),
+                             report.schema));
+        }
+        for failure in report.failures() {
+          has_failures = true;
+          let e = failure.error.as_ref().unwrap();
+          lines.push(format!("Fail to write row {}: {}",
+                             failure.row, e));
+        }
+        for row in report.rows.iter() {
+          for warning in row.warnings.iter() {
+            has_failures = true;
+            lines.push(format!("Warning in row {}: {}",
+                               row.row, warning));
+          }
+        }
+      }
+      let msg = text(lines.join("\n")).shaping(advanced_text::Shaping::Advanced);
+      if has_failures {
+        msg.color(warn_color)
+      } else {
+        msg
+      }
     } else {
       text("")
     };
 
     container(column![header,
                       header_selection,
+                      header_layout,
                       file_selection,
+                      sheet_selection,
+                      sheets_selection,
                       rows_info,
                       yaml_selection,
+                      schema_generation,
                       yaml_info,
                       invalid_field,
+                      schema_lint,
                       prefix_input,
                       output_dir,
+                      jobs_input,
                       progress,
                       completion_msg].spacing(20)
                                      .max_width(800)
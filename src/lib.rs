@@ -1,118 +1,304 @@
+pub mod cell;
+pub mod cli;
+pub mod error;
+pub mod expr;
+pub mod field_type;
 pub mod gui;
 pub mod md_gen;
+pub mod project;
 pub mod reader;
+pub mod report;
+pub mod rules;
+pub mod schema_gen;
+pub mod schema_lint;
 pub mod yaml_parser;
 
+pub use cell::Cell;
+pub use error::Db2MdError;
+pub use field_type::{EmptyPolicy, FieldFormat, FieldType};
+pub use report::{ConversionReport, RowOutcome, RowReport};
+pub use rules::{FieldRules, RulePolicy};
+pub use yaml_parser::{FieldSpec, Schema, SchemaItem, Section};
+
 use std::collections::HashMap;
+use std::sync::atomic::{AtomicUsize, Ordering};
 use std::sync::{Arc, Mutex};
 use std::thread;
 
-pub fn write_row_to_md(row: &Vec<String>,
-                       field_map: &HashMap<String, usize>,
-                       file_idx: usize,
-                       output_dir: &String,
-                       md_prefix: &String)
-                       -> usize
+// Write one row into the output directory, which convert_sheet
+// creates beforehand
+fn write_row_to_md(row: &[Cell],
+                   // in schema order
+                   fields: &[&FieldSpec],
+                   field_map: &HashMap<String, usize>,
+                   // columns tried when a cell is empty
+                   fallbacks: &HashMap<String, Vec<usize>>,
+                   file_idx: usize,
+                   options: &ConvertOptions,
+                   // error cells and cells that do not fit their
+                   // field type, rules the row breaks are already
+                   // in it and written as a callout
+                   warnings: &mut Vec<Db2MdError>)
+                   -> Result<String, Db2MdError>
 {
   let mut md_string = String::new();
-  md_gen::generate_markdown(row, field_map, &mut md_string);
+  md_gen::generate_callout(warnings, &mut md_string);
+  md_gen::generate_markdown(row,
+                            fields,
+                            field_map,
+                            fallbacks,
+                            &mut md_string,
+                            warnings)?;
   // generate filename
-  let output_path = std::path::Path::new(output_dir);
-  if !output_path.exists() {
-    if let Err(e) = std::fs::create_dir_all(output_path) {
-      eprintln!("Failed to create directory '{}': {}",
-                output_dir, e);
-      return 0usize;
-    }
-  }
-  let filename =
-    format!("{}/{}-{:03}.md", output_dir, md_prefix, file_idx);
+  let output_dir = &options.output_dir;
+  let filename = format!("{}/{}-{:03}.md",
+                         output_dir, options.md_prefix, file_idx);
   // write md file
-  if let Err(e) = std::fs::write(&filename, md_string) {
-    eprintln!("Failed to write file '{}': {}", filename, e);
-    return 0usize;
+  std::fs::write(&filename, md_string).map_err(|e| {
+                                        Db2MdError::io(&filename, e)
+                                      })?;
+  Ok(filename)
+}
+
+// Settings shared by the GUI and the CLI for one conversion
+#[derive(Debug, Clone)]
+pub struct ConvertOptions
+{
+  pub md_prefix: String,
+  pub output_dir: String,
+  pub has_header: bool,
+  // guess has_header from the data instead
+  pub detect_header: bool,
+  // position and height of the header, banner rows to ignore
+  pub header: reader::HeaderLayout,
+  // sheet and csv settings of the data file
+  pub read: reader::ReadOptions,
+  // number of worker threads writing md files
+  pub jobs: usize,
+}
+
+impl Default for ConvertOptions
+{
+  fn default() -> Self
+  {
+    Self { md_prefix: String::from("ccms-doc"),
+           output_dir: String::from("_md"),
+           has_header: false,
+           detect_header: false,
+           header: reader::HeaderLayout::default(),
+           read: reader::ReadOptions::default(),
+           jobs: default_jobs() }
   }
-  1usize
 }
 
+// One worker per available core, or a single one if unknown
+pub fn default_jobs() -> usize
+{
+  thread::available_parallelism().map(|n| n.get()).unwrap_or(1)
+}
+
+// Convert every data row of the selected sheet into
+// `{output_dir}/{md_prefix}-{idx}.md`, idx counting from the first
+// row below the header, errors that stop the whole conversion are
+// returned, failures of single rows are in the report
 pub fn process_data(excel_path: &str,
                     yaml_path: &str,
-                    // Using String for md_prefix allows for
-                    // ownership and mutability,
-                    // which is necessary when the value needs to be
-                    // cloned for each thread.
-                    // When `md_prefix` is of type `&str`, it's a
-                    //    borrowed reference with a limited
-                    //    lifetime.
-                    // We're trying to clone this reference and
-                    //    move it into a new thread.
-                    // Rust can't guarantee that the original
-                    //    string will live long enough for all
-                    //    threads to use it safely.
-                    md_prefix: &String,
-                    has_header: &bool,
+                    options: &ConvertOptions,
                     progress: &Arc<Mutex<f32>>)
-                    -> Result<(), Box<dyn std::error::Error>>
+                    -> Result<ConversionReport, Db2MdError>
+{
+  convert_sheet(excel_path,
+                yaml_path,
+                options,
+                progress,
+                (0.0, 100.0))
+}
+
+// Convert several sheets of one workbook, each paired with its own
+// schema, into `{output_dir}/{sheet}/`, one report per sheet
+pub fn process_sheets(excel_path: &str,
+                      sheet_schemas: &[(String, String)],
+                      options: &ConvertOptions,
+                      progress: &Arc<Mutex<f32>>)
+                      -> Result<Vec<ConversionReport>, Db2MdError>
+{
+  let share = 100.0 / sheet_schemas.len().max(1) as f32;
+  let mut reports = vec![];
+  for (idx, (sheet, yaml_path)) in sheet_schemas.iter().enumerate() {
+    let output_dir = std::path::Path::new(&options.output_dir)
+                       .join(sheet_dir_name(sheet));
+    let mut sheet_options = options.clone();
+    sheet_options.read.sheet =
+      reader::SheetSelector::Name(sheet.clone());
+    sheet_options.output_dir =
+      output_dir.to_string_lossy().into_owned();
+    reports.push(convert_sheet(excel_path,
+                               yaml_path,
+                               &sheet_options,
+                               progress,
+                               (idx as f32 * share, share))?);
+  }
+  Ok(reports)
+}
+
+// Sheet names may hold characters that are not allowed in paths
+fn sheet_dir_name(sheet: &str) -> String
+{
+  sheet.chars()
+       .map(|c| match c {
+         '/' | '\\' | ':' | '*' | '?' | '"' | '<' | '>' | '|' => '_',
+         _ => c,
+       })
+       .collect()
+}
+
+// Shared by process_data and process_sheets, progress moves from
+// `progress_span.0` by up to `progress_span.1` percent
+fn convert_sheet(excel_path: &str,
+                 yaml_path: &str,
+                 options: &ConvertOptions,
+                 progress: &Arc<Mutex<f32>>,
+                 progress_span: (f32, f32))
+                 -> Result<ConversionReport, Db2MdError>
 {
   // Read Excel and YAML files
-  let mut rows: Vec<Vec<String>> = vec![];
-  let meta = reader::read_excel(excel_path, &mut rows)?;
-  let tot_row_num = meta.1 as f32;
-  let schema = yaml_parser::parse_yaml_schema(yaml_path)?;
+  let mut rows: Vec<Vec<Cell>> = vec![];
+  let meta =
+    reader::read_data(excel_path, &options.read, &mut rows)?;
+  let schema = Schema::load(yaml_path)?;
 
   // Extract fields and map to columns
-  let mut fields = Vec::new();
-  yaml_parser::extract_fields(&schema, "", &mut fields);
+  let fields = schema.fields();
 
-  let mut headers = &Vec::new();
+  let mut headers = Vec::new();
 
-  if *has_header {
-    headers = &rows[0];
+  let has_header = if reader::has_column_names(excel_path) {
+    true
+  } else if options.detect_header {
+    let from = options.header.row.min(rows.len());
+    reader::detect_header(&rows[from..])
+  } else {
+    options.has_header
+  };
+  if has_header {
+    if rows.len() <= options.header.row {
+      return Err(Db2MdError::Mapping("empty sheet".to_string()));
+    }
+    headers = options.header.headers(&rows);
   }
 
   let mut invalids = vec![];
 
   let field_map = yaml_parser::map_fields_to_columns(&fields,
-                                                     headers,
+                                                     &headers,
                                                      &mut invalids);
+  let fallbacks =
+    yaml_parser::map_fallback_columns(&fields, &headers);
 
-  // Process data rows concurrently
-  let processed_rows = Arc::new(Mutex::new(0));
-  let threads: Vec<_> =
-    rows.into_iter()
-        .enumerate()
-        .map(|(idx, row)| {
-          let field_map = field_map.clone();
-          let md_prefix = md_prefix.clone();
-          let processed_rows = Arc::clone(&processed_rows);
-          let progress = Arc::clone(progress);
-          thread::spawn(move || {
-            // generate md string
-            let mut md_string = String::new();
-            md_gen::generate_markdown(&row,
-                                      &field_map,
-                                      &mut md_string);
-            // generate filename
-            let filename = format!("{}-{:03}.md", md_prefix, idx);
-            // write md file
-            if let Err(e) = std::fs::write(&filename, md_string) {
-              eprintln!("Failed to write file '{}': {}", filename, e);
-            }
-            // update progress
-            let mut count = processed_rows.lock().unwrap();
-            *count += 1;
-            let mut progress_val = progress.lock().unwrap();
-            *progress_val = *count as f32 / tot_row_num * 100.0;
-          })
-        })
-        .collect();
-
-  // Wait for all threads to complete
-  for handle in threads {
-    handle.join().expect("Thread panicked");
+  let table_rows =
+    (0..rows.len()).filter(|&idx| {
+                     options.header.is_table_row(idx, has_header)
+                   })
+                   .collect::<Vec<_>>();
+  // nothing is written if a row breaks the rules with `fail`
+  if schema.on_invalid == RulePolicy::Fail {
+    for &idx in table_rows.iter() {
+      let broken = rules::check_row(&rows[idx], &fields,
+                                    &field_map, &fallbacks);
+      if !broken.is_empty() {
+        let messages =
+          broken.iter().map(|e| e.to_string()).collect::<Vec<_>>();
+        return Err(Db2MdError::InvalidRow { row: idx,
+                                            message:
+                                              messages.join("; ") });
+      }
+    }
   }
 
-  Ok(())
+  // Create the output directory once instead of in every worker
+  std::fs::create_dir_all(&options.output_dir).map_err(|e| {
+    Db2MdError::io(&options.output_dir, e)
+  })?;
+
+  // A fixed number of workers pull the next row index until all rows
+  // are written, so large sheets do not spawn a thread per row
+  let tot_row_num = table_rows.len() as f32;
+  let next_row = AtomicUsize::new(0);
+  let processed_rows = AtomicUsize::new(0);
+  let row_reports = Mutex::new(Vec::with_capacity(table_rows.len()));
+  let jobs = options.jobs.clamp(1, table_rows.len().max(1));
+  thread::scope(|scope| {
+    for _ in 0..jobs {
+      scope.spawn(|| loop {
+             let next = next_row.fetch_add(1, Ordering::Relaxed);
+             let Some(&idx) = table_rows.get(next) else {
+               break;
+             };
+             let row = &rows[idx];
+             let mut warnings = rules::check_row(row, &fields,
+                                                 &field_map,
+                                                 &fallbacks);
+             let skip = !warnings.is_empty()
+                        && schema.on_invalid == RulePolicy::Skip;
+             // files are numbered by data row, reports keep the row
+             // of the sheet
+             let res = if skip {
+               Ok(None)
+             } else {
+               write_row_to_md(row,
+                               &fields,
+                               &field_map,
+                               &fallbacks,
+                               next,
+                               options,
+                               &mut warnings).map(Some)
+             };
+             let row_report = match res {
+               Ok(Some(output)) => {
+                 RowReport { row: idx,
+                             outcome: RowOutcome::Written,
+                             output: Some(output),
+                             error: None,
+                             warnings }
+               }
+               Ok(None) => RowReport { row: idx,
+                                       outcome:
+                                         RowOutcome::Skipped,
+                                       output: None,
+                                       error: None,
+                                       warnings },
+               Err(e) => RowReport { row: idx,
+                                     outcome: RowOutcome::Failed,
+                                     output: None,
+                                     error: Some(e),
+                                     warnings },
+             };
+             row_reports.lock().unwrap().push(row_report);
+             // update progress
+             let count =
+               processed_rows.fetch_add(1, Ordering::Relaxed) + 1;
+             let mut progress_val = progress.lock().unwrap();
+             let (start, share) = progress_span;
+             *progress_val = progress_val.max(start
+                                              + count as f32
+                                                / tot_row_num
+                                                * share);
+           });
+    }
+  });
+  // sheets without data rows are done as well
+  let (start, share) = progress_span;
+  let mut progress_val = progress.lock().unwrap();
+  *progress_val = progress_val.max(start + share);
+
+  let mut row_reports = row_reports.into_inner().unwrap();
+  row_reports.sort_by_key(|r| r.row);
+  Ok(ConversionReport { source: excel_path.to_string(),
+                        sheet: meta.0,
+                        schema: yaml_path.to_string(),
+                        output_dir: options.output_dir.clone(),
+                        invalid_fields: invalids,
+                        rows: row_reports })
 }
 
 // Unit test for the process_data function
@@ -127,17 +313,156 @@ mod tests
   {
     let excel_path = "./tests/fruit_test.xlsx".to_string();
     let yaml_path = "./tests/schema.yaml".to_string();
-    let md_prefix = "ccms-doc".to_string();
-    let has_header = false;
+    let output_dir = std::env::temp_dir().join("db2md-process-data");
+    let options = ConvertOptions { output_dir:
+                                     output_dir.to_string_lossy()
+                                               .into_owned(),
+                                   jobs: 2,
+                                   ..ConvertOptions::default() };
     let progress = Arc::new(Mutex::new(0.0));
 
-    let result = process_data(&excel_path,
-                              &yaml_path,
-                              &md_prefix,
-                              &has_header,
-                              &progress);
+    let result =
+      process_data(&excel_path, &yaml_path, &options, &progress);
     let progress_val = progress.lock().unwrap();
     assert_eq!(*progress_val, 100.0);
-    assert!(result.is_ok());
+    let report = result.unwrap();
+    assert!(report.is_success());
+    assert_eq!(report.written(), 5);
+    // without a header the first row is data too
+    let first =
+      std::fs::read_to_string(format!("{}/{}-000.md",
+                                      options.output_dir,
+                                      options.md_prefix)).unwrap();
+    assert!(first.contains("APPLE"));
+    for idx in 0..5 {
+      let filename =
+        format!("{}/{}-{:03}.md",
+                options.output_dir, options.md_prefix, idx);
+      assert!(std::path::Path::new(&filename).exists());
+    }
+  }
+
+  #[test]
+  fn test_process_data_no_rows()
+  {
+    let output_dir = std::env::temp_dir().join("db2md-process-empty");
+    let _ = std::fs::remove_dir_all(&output_dir);
+    std::fs::create_dir_all(&output_dir).unwrap();
+    let csv_path = output_dir.join("header_only.csv");
+    std::fs::write(&csv_path, "fruit,color\n").unwrap();
+    let yaml_path = output_dir.join("empty_schema.yaml");
+    std::fs::write(&yaml_path, "fruit: text\ncolor: text\n").unwrap();
+    let options = ConvertOptions { output_dir:
+                                     output_dir.to_string_lossy()
+                                               .into_owned(),
+                                   has_header: true,
+                                   ..ConvertOptions::default() };
+    let progress = Arc::new(Mutex::new(0.0));
+
+    let report = process_data(&csv_path.to_string_lossy(),
+                              &yaml_path.to_string_lossy(),
+                              &options,
+                              &progress).unwrap();
+    assert!(report.rows.is_empty());
+    // the progress ends at 100 without a single row
+    assert_eq!(*progress.lock().unwrap(), 100.0);
+  }
+
+  #[test]
+  fn test_process_data_with_header()
+  {
+    let output_dir =
+      std::env::temp_dir().join("db2md-process-header");
+    let _ = std::fs::remove_dir_all(&output_dir);
+    std::fs::create_dir_all(&output_dir).unwrap();
+    // fields named after the first row of the fruit sheet
+    let yaml_path = output_dir.join("header_schema.yaml");
+    std::fs::write(&yaml_path, "APPLE: text\nCalifornia: text\n")
+      .unwrap();
+    let options = ConvertOptions { output_dir:
+                                     output_dir.to_string_lossy()
+                                               .into_owned(),
+                                   has_header: true,
+                                   ..ConvertOptions::default() };
+    let progress = Arc::new(Mutex::new(0.0));
+
+    let report = process_data("./tests/fruit_test.xlsx",
+                              &yaml_path.to_string_lossy(),
+                              &options,
+                              &progress).unwrap();
+    assert_eq!(*progress.lock().unwrap(), 100.0);
+    assert!(report.invalid_fields.is_empty());
+    assert_eq!(report.written(), 4);
+    // reports keep the rows of the sheet
+    assert_eq!(report.rows[0].row, 1);
+    let filename = |idx: usize| {
+      format!("{}/{}-{:03}.md",
+              options.output_dir, options.md_prefix, idx)
+    };
+    let first = std::fs::read_to_string(filename(0)).unwrap();
+    assert_eq!(first,
+               "# APPLE\n\nPEARL\n\n# California\n\nIllinois\n\n");
+    assert!(!std::path::Path::new(&filename(4)).exists());
+  }
+
+  #[test]
+  fn test_process_data_rules()
+  {
+    let output_dir =
+      std::env::temp_dir().join("db2md-process-rules");
+    let _ = std::fs::remove_dir_all(&output_dir);
+    std::fs::create_dir_all(&output_dir).unwrap();
+    let yaml_path = output_dir.join("rules_schema.yaml");
+    let schema = "fruit:
+  type: text
+  enum: [APPLE, PEARL, ORANGE, BANANA]
+  max_length: 6
+color: text
+date: date
+price: {type: number, min: 2, max: 7}
+state: {type: text, regex: '[A-Z][a-z]+'}
+";
+    let options = ConvertOptions { output_dir:
+                                     output_dir.to_string_lossy()
+                                               .into_owned(),
+                                   ..ConvertOptions::default() };
+    let progress = Arc::new(Mutex::new(0.0));
+    let convert = |policy: &str| {
+      std::fs::write(&yaml_path,
+                     format!("_options:\n  on_invalid: {}\n{}",
+                             policy, schema)).unwrap();
+      process_data("./tests/fruit_test.xlsx",
+                   &yaml_path.to_string_lossy(),
+                   &options,
+                   &progress)
+    };
+
+    // rows are written with the rules they break on top
+    let report = convert("warn").unwrap();
+    assert_eq!(report.written(), 5);
+    assert_eq!(report.rows[4].warnings.len(), 2);
+    let third =
+      std::fs::read_to_string(format!("{}/{}-002.md",
+                                      options.output_dir,
+                                      options.md_prefix)).unwrap();
+    assert!(third.starts_with("> [!WARNING]\n> field price 7.99 is \
+                               above the maximum 7\n\n# fruit"));
+
+    let report = convert("skip").unwrap();
+    assert_eq!(report.written(), 2);
+    assert_eq!(report.skipped(), 3);
+    assert!(report.is_success());
+    assert_eq!(report.rows[3].warnings,
+               vec![Db2MdError::Rule { field: "price".to_string(),
+                                       message: "1.13 is below the \
+                                                 minimum 2"
+                                                   .to_string() }]);
+
+    let res = convert("fail");
+    assert_eq!(res.unwrap_err(),
+               Db2MdError::InvalidRow { row: 2,
+                                        message: "field price 7.99 is \
+                                                  above the maximum 7"
+                                                    .to_string() });
   }
 }
@@ -1,8 +1,29 @@
 #![allow(non_snake_case)]
+use clap::Parser;
+use db2md::cli::{self, Cli, Command};
 use db2md::gui::Db2MdApp;
 use iced::application;
+use std::process::ExitCode;
 
-fn main() -> iced::Result
+fn main() -> ExitCode
 {
-  application(Db2MdApp::title, Db2MdApp::update, Db2MdApp::view).theme(Db2MdApp::theme).run()
+  let cli = Cli::parse();
+  match cli.command {
+    None | Some(Command::Gui) => run_gui(),
+    Some(command) => cli::run(command),
+  }
+}
+
+fn run_gui() -> ExitCode
+{
+  let res = application(Db2MdApp::title,
+                        Db2MdApp::update,
+                        Db2MdApp::view).theme(Db2MdApp::theme)
+                                       .subscription(Db2MdApp::subscription)
+                                       .run();
+  if let Err(e) = res {
+    eprintln!("db2md: {}", e);
+    return ExitCode::FAILURE;
+  }
+  ExitCode::SUCCESS
 }
@@ -1,23 +1,92 @@
 use std::collections::{HashMap, HashSet};
 
-pub fn generate_markdown(data_row: &[String],
+use crate::cell::Cell;
+use crate::error::Db2MdError;
+use crate::expr::row_lookup;
+use crate::field_type::EmptyPolicy;
+use crate::yaml_parser::FieldSpec;
+
+// Sections follow the order of `fields` as written in the schema,
+// whatever the order of their columns, hidden fields are left out.
+// Cells are rendered as the type declared in the format of their
+// field, cells that do not fit it are kept as read and added to
+// `warnings` like error cells. `expr` fields are computed from the
+// cells of the others, failures are warnings and leave them empty.
+// Empty and error cells are replaced by the first filled cell of the
+// `fallbacks` columns of their field, or its default, else they
+// follow its empty policy, which may fail the whole row
+pub fn generate_markdown(data_row: &[Cell],
+                         fields: &[&FieldSpec],
                          field_map: &HashMap<String, usize>,
-                         output: &mut String)
+                         fallbacks: &HashMap<String, Vec<usize>>,
+                         output: &mut String,
+                         warnings: &mut Vec<Db2MdError>)
+                         -> Result<(), Db2MdError>
 {
-  // sort keys in field_map based on its value (accending)
-  let mut sorted_titles: Vec<&String> = field_map.keys().collect();
-  sorted_titles.sort_by(|&a, &b| {
-                 field_map.get(a)
-                          .unwrap()
-                          .cmp(field_map.get(b).unwrap())
-               });
   let mut implemented_title: HashSet<String> = HashSet::new();
   let mut current_path = Vec::new();
-  for &t in sorted_titles.iter() {
-    // get content from each cell, "N/A" by default
-    let default = String::from("N/A");
-    let content = data_row.get(*field_map.get(t).unwrap())
-                          .unwrap_or(&default);
+  let lookup = row_lookup(data_row, fields, field_map, fallbacks);
+  for field in fields.iter().filter(|f| !f.hidden) {
+    let t = &field.path;
+    let format = &field.format;
+    let computed;
+    let cell = match field.expr.as_ref() {
+      Some(expr) => {
+        computed = match expr.eval(&lookup) {
+          Ok(cell) => cell,
+          Err(message) => {
+            let field = t.clone();
+            warnings.push(Db2MdError::Expr { field, message });
+            Cell::Empty
+          }
+        };
+        Some(&computed)
+      }
+      None => {
+        // fields without a column are invalid and left out, unless
+        // they have a default
+        let column = field_map.get(t).copied();
+        if column.is_none() && field.default.is_none() {
+          continue;
+        }
+        let fallback = fallbacks.get(t).map(Vec::as_slice);
+        field.pick(data_row, column, fallback.unwrap_or_default())
+      }
+    };
+    if let Some(Cell::Error(value)) = cell {
+      warnings.push(Db2MdError::ErrorCell { field: t.clone(),
+                                            value: value.clone() });
+    }
+    let content = match cell {
+      // columns missing from the row stay "N/A" without a policy
+      None if format.empty == EmptyPolicy::Blank => {
+        String::from("N/A")
+      }
+      Some(cell) if !cell.is_empty() && !cell.is_error() => {
+        match format.render(cell) {
+          Ok(content) => content,
+          Err(message) => {
+            let field = t.clone();
+            warnings.push(Db2MdError::Coerce { field, message });
+            cell.to_string()
+          }
+        }
+      }
+      _ => match &format.empty {
+        EmptyPolicy::Blank => String::new(),
+        EmptyPolicy::Placeholder(placeholder) => placeholder.clone(),
+        // leave out the section and its titles
+        EmptyPolicy::Skip => continue,
+        EmptyPolicy::Fail => {
+          let reason = match cell {
+            Some(Cell::Error(value)) => format!("holds {}", value),
+            _ => String::from("is empty"),
+          };
+          return Err(Db2MdError::Render(format!("field {} {}",
+                                                t, reason)));
+        }
+      },
+    };
 
     // prepare section title
     let sections = t.split('.').collect::<Vec<_>>();
@@ -35,8 +104,27 @@ pub fn generate_markdown(data_row: &[String],
     }
 
     // append corresponding content
-    output.push_str((*content).as_str());
+    output.push_str(&content);
     output.push('\n');
     output.push('\n');
   }
+  Ok(())
+}
+
+// Rules broken by a row kept with `on_invalid: warn`, as a callout
+// above its sections
+pub fn generate_callout(warnings: &[Db2MdError], output: &mut String)
+{
+  let broken =
+    warnings.iter()
+            .filter(|w| matches!(w, Db2MdError::Rule { .. }))
+            .collect::<Vec<_>>();
+  if broken.is_empty() {
+    return;
+  }
+  output.push_str("> [!WARNING]\n");
+  for rule in broken {
+    output.push_str(&format!("> {}\n", rule));
+  }
+  output.push('\n');
 }
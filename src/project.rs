@@ -0,0 +1,138 @@
+use std::fs::read_to_string;
+use std::path::Path;
+use yaml_rust2::{Yaml, YamlLoader};
+
+use crate::error::Db2MdError;
+
+// Project config file binding sheets of a workbook to schemas, e.g.
+//
+// schema: schema.yaml
+// sheets:
+//   Records: records.yaml
+//   Vegetables:
+//
+// sheets without a schema use the top-level one, relative paths are
+// resolved against the directory of the config file
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ProjectConfig
+{
+  // schema for sheets without their own
+  pub schema: Option<String>,
+  // sheets to convert with an optional own schema, all if empty
+  pub sheets: Vec<(String, Option<String>)>,
+}
+
+impl ProjectConfig
+{
+  pub fn load(file_path: &str) -> Result<Self, Db2MdError>
+  {
+    let contents =
+      read_to_string(file_path).map_err(|e| {
+                                 Db2MdError::io(file_path, e)
+                               })?;
+    let docs = YamlLoader::load_from_str(&contents).map_err(|e| {
+                 Db2MdError::Schema(format!("{}: {}", file_path, e))
+               })?;
+    let base_dir =
+      Path::new(file_path).parent().unwrap_or(Path::new(""));
+    let invalid = |msg: &str| {
+      Db2MdError::Schema(format!("{}: {}", file_path, msg))
+    };
+    let resolve =
+      |value: &Yaml| -> Result<Option<String>, Db2MdError> {
+        match value {
+          Yaml::Null => Ok(None),
+          Yaml::String(path) => Ok(Some(base_dir.join(path)
+                                                .to_string_lossy()
+                                                .into_owned())),
+          _ => Err(invalid("schema must be a file path")),
+        }
+      };
+
+    let mut config = ProjectConfig::default();
+    let Some(doc) = docs.first() else {
+      return Ok(config);
+    };
+    config.schema = resolve(&doc["schema"])?;
+    match &doc["sheets"] {
+      Yaml::BadValue | Yaml::Null => {}
+      Yaml::Hash(hash) => {
+        for (name, schema) in hash {
+          let name = sheet_name(name).ok_or_else(|| {
+                                       invalid("invalid sheet name")
+                                     })?;
+          config.sheets.push((name, resolve(schema)?));
+        }
+      }
+      Yaml::Array(names) => {
+        for name in names {
+          let name = sheet_name(name).ok_or_else(|| {
+                                       invalid("invalid sheet name")
+                                     })?;
+          config.sheets.push((name, None));
+        }
+      }
+      _ => {
+        return Err(invalid("sheets must be a mapping or a list"))
+      }
+    }
+    Ok(config)
+  }
+
+  // Restrict the conversion to `names`, keeping schemas set for them
+  pub fn select(&mut self, names: &[String])
+  {
+    self.sheets = names.iter()
+                       .map(|name| {
+                         let schema =
+                           self.sheets
+                               .iter()
+                               .find(|(sheet, _)| sheet == name)
+                               .and_then(|(_, schema)| schema.clone());
+                         (name.clone(), schema)
+                       })
+                       .collect();
+  }
+
+  // Pair every sheet to convert with its schema, sheets of the
+  // config must exist in `sheet_names`
+  pub fn sheet_schemas(
+    &self,
+    sheet_names: &[String])
+    -> Result<Vec<(String, String)>, Db2MdError>
+  {
+    let no_schema = |sheet: &str| {
+      Db2MdError::Schema(format!("no schema for sheet {}", sheet))
+    };
+    if self.sheets.is_empty() {
+      let schema =
+        self.schema.as_ref().ok_or_else(|| no_schema("*"))?;
+      return Ok(sheet_names.iter()
+                           .map(|s| (s.clone(), schema.clone()))
+                           .collect());
+    }
+    let mut res = vec![];
+    for (sheet, schema) in self.sheets.iter() {
+      if !sheet_names.contains(sheet) {
+        return Err(Db2MdError::Workbook(format!("no sheet {} in {:?}",
+                                                sheet, sheet_names)));
+      }
+      let schema = schema.as_ref()
+                         .or(self.schema.as_ref())
+                         .ok_or_else(|| no_schema(sheet))?;
+      res.push((sheet.clone(), schema.clone()));
+    }
+    Ok(res)
+  }
+}
+
+// Sheet names may look like numbers in yaml
+fn sheet_name(yaml: &Yaml) -> Option<String>
+{
+  match yaml {
+    Yaml::String(s) => Some(s.clone()),
+    Yaml::Integer(i) => Some(i.to_string()),
+    Yaml::Real(r) => Some(r.clone()),
+    _ => None,
+  }
+}
@@ -1,63 +1,289 @@
-use calamine::{open_workbook, Data, Reader, Xlsx};
-use chrono::format::strftime::StrftimeItems;
+use calamine::{open_workbook_auto, Data, Reader, Sheets};
+use chrono::{NaiveDate, NaiveDateTime, NaiveTime};
+use std::fs::File;
+use std::io::BufReader;
 
-// Write data to a 2D string vector
+use crate::cell::{parse_iso_duration, Cell};
+use crate::error::Db2MdError;
+
+pub mod delimited;
+pub mod json;
+pub mod sqlite;
+
+pub use delimited::{detect_header, CsvOptions, TextEncoding};
+
+// Table name, tot row/col numbers of a read sheet
+pub type SheetMeta = (String, usize, usize);
+
+// Sheet of a workbook to read, by name or zero-based position
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SheetSelector
+{
+  Name(String),
+  Index(usize),
+}
+
+impl Default for SheetSelector
+{
+  fn default() -> Self
+  {
+    SheetSelector::Index(0)
+  }
+}
+
+// A number selects by position, anything else by name
+impl std::str::FromStr for SheetSelector
+{
+  type Err = std::convert::Infallible;
+
+  fn from_str(s: &str) -> Result<Self, Self::Err>
+  {
+    Ok(match s.parse::<usize>() {
+         Ok(idx) => SheetSelector::Index(idx),
+         Err(_) => SheetSelector::Name(s.to_string()),
+       })
+  }
+}
+
+impl std::fmt::Display for SheetSelector
+{
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>)
+         -> std::fmt::Result
+  {
+    match self {
+      SheetSelector::Name(name) => write!(f, "{}", name),
+      SheetSelector::Index(idx) => write!(f, "{}", idx),
+    }
+  }
+}
+
+// Everything needed to pick the table to read from a data file
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ReadOptions
+{
+  pub sheet: SheetSelector,
+  pub csv: CsvOptions,
+  // SQL run against a SQLite database instead of reading a table
+  pub query: Option<String>,
+}
+
+// xlsx, xlsm, xls, xlsb and ods are told apart by calamine from the
+// extension, or from the content when the extension is unknown
+fn open_sheets(file_path: &str)
+               -> Result<Sheets<BufReader<File>>, Db2MdError>
+{
+  open_workbook_auto(file_path).map_err(|e| {
+    Db2MdError::Workbook(format!("{}: {}", file_path, e))
+  })
+}
+
+// Names of all sheets in workbook order, or tables of a database
+pub fn list_sheets(file_path: &str)
+                   -> Result<Vec<String>, Db2MdError>
+{
+  if delimited::is_delimited(file_path) || json::is_json(file_path) {
+    return Ok(vec![delimited::table_name(file_path)]);
+  }
+  if sqlite::is_sqlite(file_path) {
+    return sqlite::list_tables(file_path);
+  }
+  Ok(open_sheets(file_path)?.sheet_names())
+}
+
+// Sources whose first row always holds column names
+pub fn has_column_names(file_path: &str) -> bool
+{
+  sqlite::is_sqlite(file_path) || json::is_json(file_path)
+}
+
+// Where the table starts in a sheet: rows above `row` are a banner
+// and ignored, the header spans `rows` rows from `row` and `skip` rows
+// between the header and the data are left out. Without a header the
+// data starts `skip` rows below `row`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct HeaderLayout
+{
+  pub row: usize,
+  pub rows: usize,
+  pub skip: usize,
+}
+
+impl Default for HeaderLayout
+{
+  fn default() -> Self
+  {
+    Self { row: 0, rows: 1, skip: 0 }
+  }
+}
+
+impl HeaderLayout
+{
+  // Column titles, a header of several rows gives dotted paths
+  pub fn headers(&self, sheet: &[Vec<Cell>]) -> Vec<String>
+  {
+    let start = self.row.min(sheet.len());
+    let end = (self.row + self.rows.max(1)).min(sheet.len());
+    merge_header_rows(&sheet[start..end])
+  }
+
+  // Rows below the banner and the header, past the skipped ones
+  pub fn is_table_row(&self, idx: usize, has_header: bool) -> bool
+  {
+    let header_rows = if has_header { self.rows.max(1) } else { 0 };
+    idx >= self.row + header_rows + self.skip
+  }
+}
+
+// Column titles of the first row, empty for an empty sheet
+pub fn header_names(rows: &[Vec<Cell>]) -> Vec<String>
+{
+  HeaderLayout::default().headers(rows)
+}
+
+// Join the titles of each column top-down with dots, e.g. a `customer`
+// group over `name` and `department` gives `customer.name` and
+// `customer.department`. A merged group title only fills its first
+// cell, so it runs on to the right until the next title or the end of
+// its parent group
+pub fn merge_header_rows(rows: &[Vec<Cell>]) -> Vec<String>
+{
+  let width = rows.iter().map(|r| r.len()).max().unwrap_or(0);
+  let mut paths: Vec<Vec<String>> = vec![vec![]; width];
+  for (depth, row) in rows.iter().enumerate() {
+    let last = depth + 1 == rows.len();
+    let parents = paths.clone();
+    let mut group = String::new();
+    for col in 0..width {
+      if col > 0 && parents[col] != parents[col - 1] {
+        group.clear();
+      }
+      let title = row.get(col)
+                     .map(|c| c.to_string().trim().to_string())
+                     .unwrap_or_default();
+      if !title.is_empty() {
+        group = title.clone();
+      }
+      let part = if last { title } else { group.clone() };
+      if !part.is_empty() {
+        paths[col].push(part);
+      }
+    }
+  }
+  paths.into_iter().map(|p| p.join(".")).collect()
+}
+
+// Read any supported data file, the reader is picked from the file
+// extension
+pub fn read_data(file_path: &str,
+                 options: &ReadOptions,
+                 sheet: &mut Vec<Vec<Cell>>)
+                 -> Result<SheetMeta, Db2MdError>
+{
+  if delimited::is_delimited(file_path) {
+    return delimited::read_csv(file_path, &options.csv, sheet);
+  }
+  if json::is_json(file_path) {
+    return json::read_json(file_path, sheet);
+  }
+  if sqlite::is_sqlite(file_path) {
+    return sqlite::read_sqlite(file_path,
+                               &options.sheet,
+                               options.query.as_deref(),
+                               sheet);
+  }
+  read_excel_sheet(file_path, &options.sheet, sheet)
+}
+
+// Write data of the first sheet to a 2D cell vector
 // return string name, tot row/col numbers
 pub fn read_excel(file_path: &str,
-                  sheet: &mut Vec<Vec<String>>)
-                  -> Result<(String, usize, usize), String>
+                  sheet: &mut Vec<Vec<Cell>>)
+                  -> Result<(String, usize, usize), Db2MdError>
 {
-  let mut workbook: Xlsx<_> =
-    open_workbook(file_path).expect("db2md: cannot open given xlsx \
-                                     file");
-  let sheet_name = workbook.sheet_names()[0].to_owned();
+  read_excel_sheet(file_path, &SheetSelector::default(), sheet)
+}
+
+// Same as read_excel for the sheet picked by `selector`
+pub fn read_excel_sheet(file_path: &str,
+                        selector: &SheetSelector,
+                        sheet: &mut Vec<Vec<Cell>>)
+                        -> Result<SheetMeta, Db2MdError>
+{
+  let mut workbook = open_sheets(file_path)?;
+  let sheet_names = workbook.sheet_names();
+  let sheet_name = match selector {
+    SheetSelector::Name(name) => {
+      sheet_names.iter().find(|&s| s == name)
+    }
+    SheetSelector::Index(idx) => sheet_names.get(*idx),
+  };
+  let Some(sheet_name) = sheet_name.cloned() else {
+    return Err(Db2MdError::Workbook(format!("{}: no sheet {} in {:?}",
+                                            file_path,
+                                            selector,
+                                            sheet_names)));
+  };
   if let Ok(range) = workbook.worksheet_range(&sheet_name) {
-    let row_number = range.get_size().0;
-    let col_number = range.get_size().1;
-    println!("db2md: found {row_number} rows in {sheet_name}");
-    for row in range.rows().into_iter() {
-      sheet.push(parse_row(row));
+    // the range starts at the first filled cell, rows and columns
+    // before it are padded so that indexes and letters match the sheet
+    let (first_row, first_col) = range.start().unwrap_or_default();
+    let (first_row, first_col) = (first_row as usize, first_col as usize);
+    let row_number = first_row + range.get_size().0;
+    let col_number = first_col + range.get_size().1;
+    for _ in 0..first_row {
+      sheet.push(vec![Cell::Empty; col_number]);
+    }
+    for row in range.rows() {
+      let mut cells = vec![Cell::Empty; first_col];
+      cells.extend(parse_row(row));
+      sheet.push(cells);
     }
     return Ok((sheet_name, row_number, col_number));
   }
-  Err("db2md: cannot read the sheet".to_string())
+  Err(Db2MdError::Workbook(format!("{}: cannot read {}",
+                                   file_path, sheet_name)))
 }
 
-fn parse_row(row: &[Data]) -> Vec<String>
+fn parse_row(row: &[Data]) -> Vec<Cell>
 {
-  // YYYY-MM-DD
-  let fmt = StrftimeItems::new("%Y-%m-%d");
-  let mut res: Vec<String> = vec![];
-  for cell in row.iter() {
-    match cell {
-      Data::Int(x) => {
-        let s = x.to_string();
-        res.push(s);
-      }
-      Data::Float(x) => {
-        let s = x.to_string();
-        res.push(s);
-      }
-      Data::Bool(b) => {
-        if *b {
-          res.push(String::from("true"))
-        } else {
-          res.push(String::from("false"))
-        }
+  row.iter().map(parse_cell).collect()
+}
+
+fn parse_cell(cell: &Data) -> Cell
+{
+  match cell {
+    Data::Int(x) => Cell::Int(*x),
+    Data::Float(x) => Cell::Float(*x),
+    Data::Bool(b) => Cell::Bool(*b),
+    Data::DateTime(t) if t.is_duration() => {
+      Cell::Duration(t.as_duration().unwrap_or_default())
+    }
+    Data::DateTime(t) => match t.as_datetime() {
+      // serials below one day only hold a time of day
+      Some(datetime) if (0.0..1.0).contains(&t.as_f64()) => {
+        Cell::Time(datetime.time())
       }
-      Data::DateTime(t) => {
-        if let Some(date) = t.as_datetime() {
-          let s = date.format_with_items(fmt.clone()).to_string();
-          res.push(s)
-        } else {
-          res.push(String::from(""))
-        }
+      Some(datetime) => Cell::from_datetime(datetime),
+      None => Cell::Error(format!("invalid date {}", t)),
+    },
+    // ods stores dates and durations as ISO 8601 text
+    Data::DateTimeIso(s) => {
+      if let Ok(datetime) = s.parse::<NaiveDateTime>() {
+        Cell::from_datetime(datetime)
+      } else if let Ok(date) = s.parse::<NaiveDate>() {
+        Cell::Date(date)
+      } else if let Ok(time) = s.parse::<NaiveTime>() {
+        Cell::Time(time)
+      } else {
+        Cell::Text(s.to_string())
       }
-      Data::String(s)
-      | Data::DateTimeIso(s)
-      | Data::DurationIso(s) => res.push(s.to_string()),
-      _ => res.push(String::from("")),
     }
+    Data::DurationIso(s) => match parse_iso_duration(s) {
+      Some(duration) => Cell::Duration(duration),
+      None => Cell::Text(s.to_string()),
+    },
+    Data::String(s) => Cell::Text(s.to_string()),
+    Data::Error(e) => Cell::Error(e.to_string()),
+    Data::Empty => Cell::Empty,
   }
-  return res;
 }
@@ -0,0 +1,187 @@
+use encoding_rs::{Encoding, GBK, UTF_16LE, UTF_8};
+use std::path::Path;
+
+use super::SheetMeta;
+use crate::cell::Cell;
+use crate::error::Db2MdError;
+
+// Text encodings accepted for delimited files
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum TextEncoding
+{
+  // BOM if present, else UTF-8 with a fallback to GBK
+  #[default]
+  Auto,
+  Utf8,
+  Gbk,
+  // byte order taken from the BOM, little endian without one
+  Utf16,
+}
+
+impl std::str::FromStr for TextEncoding
+{
+  type Err = String;
+
+  fn from_str(s: &str) -> Result<Self, Self::Err>
+  {
+    match s.to_lowercase().replace(['-', '_'], "").as_str() {
+      "auto" => Ok(TextEncoding::Auto),
+      "utf8" => Ok(TextEncoding::Utf8),
+      "gbk" | "gb2312" | "gb18030" => Ok(TextEncoding::Gbk),
+      "utf16" => Ok(TextEncoding::Utf16),
+      _ => Err(format!("unknown encoding {}, expected auto, utf-8, gbk \
+                        or utf-16",
+                       s)),
+    }
+  }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct CsvOptions
+{
+  // field separator, `\t` for .tsv files and `,` otherwise if unset
+  pub delimiter: Option<u8>,
+  // quote character, `"` if unset
+  pub quote: Option<u8>,
+  pub encoding: TextEncoding,
+}
+
+// Extensions handled by read_csv instead of calamine
+pub fn is_delimited(file_path: &str) -> bool
+{
+  let ext =
+    Path::new(file_path).extension()
+                        .map(|e| e.to_string_lossy().to_lowercase());
+  matches!(ext.as_deref(), Some("csv" | "tsv" | "txt"))
+}
+
+// A delimited file holds a single table named after the file
+pub fn table_name(file_path: &str) -> String
+{
+  Path::new(file_path).file_stem()
+                      .map(|s| s.to_string_lossy().into_owned())
+                      .unwrap_or_else(|| file_path.to_string())
+}
+
+// Write a CSV/TSV file to a 2D cell vector like read_excel, values
+// carry no type and are kept as text
+pub fn read_csv(file_path: &str,
+                options: &CsvOptions,
+                sheet: &mut Vec<Vec<Cell>>)
+                -> Result<SheetMeta, Db2MdError>
+{
+  let bytes = std::fs::read(file_path).map_err(|e| {
+                                        Db2MdError::io(file_path, e)
+                                      })?;
+  let contents =
+    decode(&bytes, options.encoding).ok_or_else(|| {
+      Db2MdError::Workbook(format!("{}: not encoded in {:?}",
+                                   file_path, options.encoding))
+    })?;
+
+  let tsv =
+    Path::new(file_path).extension().is_some_and(|e| {
+                                      e.eq_ignore_ascii_case("tsv")
+                                    });
+  let delimiter =
+    options.delimiter.unwrap_or(if tsv { b'\t' } else { b',' });
+  let mut reader =
+    csv::ReaderBuilder::new().has_headers(false)
+                             .flexible(true)
+                             .delimiter(delimiter)
+                             .quote(options.quote.unwrap_or(b'"'))
+                             .from_reader(contents.as_bytes());
+  let mut row_number = 0;
+  let mut col_number = 0;
+  for record in reader.records() {
+    let record = record.map_err(|e| {
+                         Db2MdError::Workbook(format!("{}: {}",
+                                                      file_path, e))
+                       })?;
+    let row = record.iter().map(text_cell).collect::<Vec<_>>();
+    row_number += 1;
+    col_number = col_number.max(row.len());
+    sheet.push(row);
+  }
+  Ok((table_name(file_path), row_number, col_number))
+}
+
+fn text_cell(value: &str) -> Cell
+{
+  if value.is_empty() {
+    Cell::Empty
+  } else {
+    Cell::Text(value.to_string())
+  }
+}
+
+// Decode raw bytes, a BOM always wins over the requested encoding
+fn decode(bytes: &[u8], encoding: TextEncoding) -> Option<String>
+{
+  if let Some((bom_encoding, bom_len)) = Encoding::for_bom(bytes) {
+    return strict_decode(bom_encoding, &bytes[bom_len..]);
+  }
+  match encoding {
+    TextEncoding::Auto => {
+      strict_decode(UTF_8, bytes).or_else(|| {
+                                   strict_decode(GBK, bytes)
+                                 })
+    }
+    TextEncoding::Utf8 => strict_decode(UTF_8, bytes),
+    TextEncoding::Gbk => strict_decode(GBK, bytes),
+    TextEncoding::Utf16 => strict_decode(UTF_16LE, bytes),
+  }
+}
+
+fn strict_decode(encoding: &'static Encoding,
+                 bytes: &[u8])
+                 -> Option<String>
+{
+  let decoded = encoding.decode_without_bom_handling_and_without_replacement(bytes)?;
+  Some(decoded.into_owned())
+}
+
+// Guess whether the first row holds column titles: a column votes for
+// a header when its first cell is text over numbers, or when its
+// first cell length stands out from equally long values below
+pub fn detect_header(rows: &[Vec<Cell>]) -> bool
+{
+  let Some((first, data)) = rows.split_first() else {
+    return false;
+  };
+  if data.is_empty()
+     || first.iter()
+             .any(|c| c.is_empty() || c.is_number() || !is_text(c))
+  {
+    return false;
+  }
+  let mut votes = 0i32;
+  for (col, title) in first.iter().enumerate() {
+    let values = data.iter()
+                     .filter_map(|r| r.get(col))
+                     .filter(|v| !v.is_empty())
+                     .collect::<Vec<_>>();
+    if values.is_empty() {
+      continue;
+    }
+    if values.iter().all(|v| v.is_number() || !is_text(v)) {
+      votes += 1;
+      continue;
+    }
+    let len = values[0].to_string().chars().count();
+    if values.iter().all(|v| v.to_string().chars().count() == len) {
+      if title.to_string().chars().count() != len {
+        votes += 1;
+      } else {
+        votes -= 1;
+      }
+    }
+  }
+  votes > 0
+}
+
+// Titles are text, typed values such as dates are data
+fn is_text(cell: &Cell) -> bool
+{
+  matches!(cell, Cell::Text(_))
+}
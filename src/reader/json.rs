@@ -0,0 +1,146 @@
+use serde_json::Value;
+use std::collections::HashMap;
+use std::path::Path;
+
+use super::delimited::table_name;
+use super::SheetMeta;
+use crate::cell::Cell;
+use crate::error::Db2MdError;
+
+// JSON arrays of records, or one record per line for ndjson/jsonl
+pub fn is_json(file_path: &str) -> bool
+{
+  let ext =
+    Path::new(file_path).extension()
+                        .map(|e| e.to_string_lossy().to_lowercase());
+  matches!(ext.as_deref(), Some("json" | "ndjson" | "jsonl"))
+}
+
+// Write the records of a JSON file to a 2D string vector, the first
+// row holds the dotted key paths of all records in order of
+// appearance, e.g. `customer.department.product`
+pub fn read_json(file_path: &str,
+                 sheet: &mut Vec<Vec<Cell>>)
+                 -> Result<SheetMeta, Db2MdError>
+{
+  let contents =
+    std::fs::read_to_string(file_path).map_err(|e| {
+                                        Db2MdError::io(file_path, e)
+                                      })?;
+  let contents = contents.trim_start_matches('\u{feff}');
+  let records = parse_records(contents).map_err(|e| {
+                  Db2MdError::Workbook(format!("{}: {}",
+                                               file_path, e))
+                })?;
+
+  let mut headers: Vec<String> = vec![];
+  let mut columns: HashMap<String, usize> = HashMap::new();
+  let mut flat_records = Vec::with_capacity(records.len());
+  for record in records.iter() {
+    let mut flat = vec![];
+    flatten("", record, &mut flat);
+    for (key, _) in flat.iter() {
+      if !columns.contains_key(key) {
+        columns.insert(key.clone(), headers.len());
+        headers.push(key.clone());
+      }
+    }
+    flat_records.push(flat);
+  }
+
+  let col_number = headers.len();
+  // the header row counts like in the other readers
+  let row_number = flat_records.len() + 1;
+  sheet.push(headers.into_iter().map(Cell::from).collect());
+  for flat in flat_records {
+    let mut row = vec![Cell::Empty; col_number];
+    for (key, value) in flat {
+      row[columns[&key]] = value;
+    }
+    sheet.push(row);
+  }
+  Ok((table_name(file_path), row_number, col_number))
+}
+
+// A whole document holding an array or a single object, else one
+// record per non-empty line
+fn parse_records(contents: &str) -> Result<Vec<Value>, String>
+{
+  let values = match serde_json::from_str::<Value>(contents) {
+    Ok(Value::Array(values)) => values,
+    Ok(value) => vec![value],
+    Err(_) => {
+      let mut values = vec![];
+      for (idx, line) in contents.lines().enumerate() {
+        if line.trim().is_empty() {
+          continue;
+        }
+        let value =
+          serde_json::from_str::<Value>(line).map_err(|e| {
+                                               format!("line {}: {}",
+                                                       idx + 1,
+                                                       e)
+                                             })?;
+        values.push(value);
+      }
+      values
+    }
+  };
+  match values.iter().position(|v| !v.is_object()) {
+    Some(idx) => Err(format!("record {} is not an object", idx + 1)),
+    None => Ok(values),
+  }
+}
+
+// Nested objects become dotted paths, arrays of plain values are
+// joined and other arrays are indexed like `items.0.name`
+fn flatten(prefix: &str,
+           value: &Value,
+           flat: &mut Vec<(String, Cell)>)
+{
+  match value {
+    Value::Object(map) => {
+      for (key, value) in map {
+        flatten(&join_key(prefix, key), value, flat);
+      }
+    }
+    Value::Array(items)
+      if items.iter().any(|v| v.is_object() || v.is_array()) =>
+    {
+      for (idx, item) in items.iter().enumerate() {
+        flatten(&join_key(prefix, &idx.to_string()), item, flat);
+      }
+    }
+    Value::Array(items) => {
+      let joined = items.iter()
+                        .map(|v| value_to_cell(v).to_string())
+                        .collect::<Vec<_>>()
+                        .join(", ");
+      flat.push((prefix.to_string(), Cell::Text(joined)));
+    }
+    _ => flat.push((prefix.to_string(), value_to_cell(value))),
+  }
+}
+
+fn join_key(prefix: &str, key: &str) -> String
+{
+  if prefix.is_empty() {
+    key.to_string()
+  } else {
+    format!("{}.{}", prefix, key)
+  }
+}
+
+fn value_to_cell(value: &Value) -> Cell
+{
+  match value {
+    Value::Null => Cell::Empty,
+    Value::Bool(b) => Cell::Bool(*b),
+    Value::Number(n) => match n.as_i64() {
+      Some(i) => Cell::Int(i),
+      None => Cell::Float(n.as_f64().unwrap_or(f64::NAN)),
+    },
+    Value::String(s) => Cell::Text(s.clone()),
+    _ => Cell::Text(value.to_string()),
+  }
+}
@@ -0,0 +1,128 @@
+use rusqlite::types::ValueRef;
+use rusqlite::{Connection, OpenFlags};
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
+
+use super::{SheetMeta, SheetSelector};
+use crate::cell::Cell;
+use crate::error::Db2MdError;
+
+// Every SQLite database starts with this header
+const SQLITE_MAGIC: &[u8; 16] = b"SQLite format 3\0";
+
+// Known extensions, or the SQLite header for any other file name
+pub fn is_sqlite(file_path: &str) -> bool
+{
+  let ext =
+    Path::new(file_path).extension()
+                        .map(|e| e.to_string_lossy().to_lowercase());
+  if matches!(ext.as_deref(), Some("db" | "sqlite" | "sqlite3")) {
+    return true;
+  }
+  let mut magic = [0u8; 16];
+  let read =
+    File::open(file_path).and_then(|mut f| f.read_exact(&mut magic));
+  read.is_ok() && &magic == SQLITE_MAGIC
+}
+
+// The database is only read, queries cannot change it
+fn open(file_path: &str) -> Result<Connection, Db2MdError>
+{
+  let flags = OpenFlags::SQLITE_OPEN_READ_ONLY;
+  Connection::open_with_flags(file_path, flags).map_err(|e| {
+                                                 sql_error(file_path,
+                                                           e)
+                                               })
+}
+
+fn sql_error(file_path: &str, e: rusqlite::Error) -> Db2MdError
+{
+  Db2MdError::Workbook(format!("{}: {}", file_path, e))
+}
+
+// User tables play the role of sheets
+pub fn list_tables(file_path: &str)
+                   -> Result<Vec<String>, Db2MdError>
+{
+  let conn = open(file_path)?;
+  let mut stmt =
+    conn.prepare("SELECT name FROM sqlite_master WHERE \
+                               type = 'table' AND name NOT LIKE \
+                               'sqlite_%' ORDER BY rowid")
+        .map_err(|e| sql_error(file_path, e))?;
+  let names =
+    stmt.query_map([], |row| row.get::<_, String>(0))
+        .and_then(|rows| rows.collect::<Result<Vec<_>, _>>())
+        .map_err(|e| sql_error(file_path, e))?;
+  Ok(names)
+}
+
+// Write the result of `query`, or of the whole table picked by
+// `selector`, to a 2D string vector, column names come first
+pub fn read_sqlite(file_path: &str,
+                   selector: &SheetSelector,
+                   query: Option<&str>,
+                   sheet: &mut Vec<Vec<Cell>>)
+                   -> Result<SheetMeta, Db2MdError>
+{
+  let (name, sql) = match query {
+    Some(sql) => (String::from("query"), sql.to_string()),
+    None => {
+      let tables = list_tables(file_path)?;
+      let table = match selector {
+        SheetSelector::Name(name) => {
+          tables.iter().find(|&t| t == name)
+        }
+        SheetSelector::Index(idx) => tables.get(*idx),
+      };
+      let Some(table) = table else {
+        return Err(Db2MdError::Workbook(format!("{}: no table {} in \
+                                                 {:?}",
+                                                file_path, selector,
+                                                tables)));
+      };
+      let sql =
+        format!("SELECT * FROM \"{}\"", table.replace('"', "\"\""));
+      (table.clone(), sql)
+    }
+  };
+
+  let conn = open(file_path)?;
+  let mut stmt =
+    conn.prepare(&sql).map_err(|e| sql_error(file_path, e))?;
+  let headers = stmt.column_names()
+                    .into_iter()
+                    .map(Cell::from)
+                    .collect::<Vec<_>>();
+  let col_number = headers.len();
+  sheet.push(headers);
+  let mut row_number = 1;
+  let mut rows =
+    stmt.query([]).map_err(|e| sql_error(file_path, e))?;
+  while let Some(row) =
+    rows.next().map_err(|e| sql_error(file_path, e))?
+  {
+    let mut res = Vec::with_capacity(col_number);
+    for idx in 0..col_number {
+      let value =
+        row.get_ref(idx).map_err(|e| sql_error(file_path, e))?;
+      res.push(value_to_cell(value));
+    }
+    sheet.push(res);
+    row_number += 1;
+  }
+  Ok((name, row_number, col_number))
+}
+
+fn value_to_cell(value: ValueRef<'_>) -> Cell
+{
+  match value {
+    ValueRef::Null => Cell::Empty,
+    ValueRef::Integer(i) => Cell::Int(i),
+    ValueRef::Real(f) => Cell::Float(f),
+    ValueRef::Text(t) | ValueRef::Blob(t) => {
+      Cell::Text(String::from_utf8_lossy(t).into_owned())
+    }
+  }
+}
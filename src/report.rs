@@ -0,0 +1,83 @@
+use crate::error::Db2MdError;
+use serde::Serialize;
+
+// What happened to a single row during a conversion
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RowOutcome
+{
+  Written,
+  // left out for breaking the rules of the schema
+  Skipped,
+  Failed,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct RowReport
+{
+  // index of the row in the sheet
+  pub row: usize,
+  pub outcome: RowOutcome,
+  // generated md file, if any
+  pub output: Option<String>,
+  pub error: Option<Db2MdError>,
+  // error cells, cells rendered as read because they do not fit
+  // their field type and broken rules
+  pub warnings: Vec<Db2MdError>,
+}
+
+// Result of process_data, one entry per converted row
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct ConversionReport
+{
+  pub source: String,
+  pub sheet: String,
+  pub schema: String,
+  pub output_dir: String,
+  // schema fields that match no column and are left out
+  pub invalid_fields: Vec<String>,
+  pub rows: Vec<RowReport>,
+}
+
+impl ConversionReport
+{
+  pub fn written(&self) -> usize
+  {
+    self.rows
+        .iter()
+        .filter(|r| r.outcome == RowOutcome::Written)
+        .count()
+  }
+
+  pub fn skipped(&self) -> usize
+  {
+    self.rows
+        .iter()
+        .filter(|r| r.outcome == RowOutcome::Skipped)
+        .count()
+  }
+
+  pub fn failures(&self) -> Vec<&RowReport>
+  {
+    self.rows
+        .iter()
+        .filter(|r| r.outcome == RowOutcome::Failed)
+        .collect()
+  }
+
+  pub fn warnings(&self) -> usize
+  {
+    self.rows.iter().map(|r| r.warnings.len()).sum()
+  }
+
+  pub fn is_success(&self) -> bool
+  {
+    self.failures().is_empty()
+  }
+
+  pub fn to_json(&self) -> String
+  {
+    // all fields are plain data, serializing cannot fail
+    serde_json::to_string_pretty(self).unwrap_or_default()
+  }
+}
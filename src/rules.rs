@@ -0,0 +1,254 @@
+use regex::Regex;
+use std::collections::HashMap;
+use std::fmt;
+use yaml_rust2::Yaml;
+
+use crate::cell::Cell;
+use crate::error::Db2MdError;
+use crate::expr::row_lookup;
+use crate::yaml_parser::FieldSpec;
+
+// Keys of a field written as a hash that constrain its cells, e.g.
+// `status: {type: text, enum: [open, closed, pending]}`
+pub const RULE_KEYS: [&str; 6] =
+  ["required", "enum", "regex", "min", "max", "max_length"];
+
+// What happens to a row breaking a rule, set with `on_invalid` under
+// `_options`
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum RulePolicy
+{
+  // write no file, report the row as skipped
+  Skip,
+  // write the row with a warning callout on top
+  #[default]
+  Warn,
+  // stop the conversion before any file is written
+  Fail,
+}
+
+impl RulePolicy
+{
+  pub fn from_name(s: &str) -> Option<Self>
+  {
+    match s.trim().to_lowercase().as_str() {
+      "skip" => Some(RulePolicy::Skip),
+      "warn" => Some(RulePolicy::Warn),
+      "fail" => Some(RulePolicy::Fail),
+      _ => None,
+    }
+  }
+}
+
+// A regex matching whole values, compared by its source
+#[derive(Clone)]
+pub struct Pattern
+{
+  source: String,
+  regex: Regex,
+}
+
+impl Pattern
+{
+  pub fn new(source: &str) -> Result<Self, String>
+  {
+    // the message ends with what is wrong, after a drawing of
+    // where in the source
+    let regex =
+      Regex::new(&format!("^(?:{})$", source)).map_err(|e| {
+        let message = e.to_string();
+        let last = message.lines().last().unwrap_or_default();
+        last.trim_start_matches("error: ").to_string()
+      })?;
+    Ok(Self { source: source.to_string(), regex })
+  }
+
+  pub fn as_str(&self) -> &str
+  {
+    &self.source
+  }
+
+  pub fn is_match(&self, s: &str) -> bool
+  {
+    self.regex.is_match(s)
+  }
+}
+
+impl PartialEq for Pattern
+{
+  fn eq(&self, other: &Self) -> bool
+  {
+    self.source == other.source
+  }
+}
+
+impl fmt::Debug for Pattern
+{
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result
+  {
+    write!(f, "Pattern({:?})", self.source)
+  }
+}
+
+// Constraints on the cells of one field
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct FieldRules
+{
+  // empty and error cells break it
+  pub required: bool,
+  // allowed values, any if empty
+  pub one_of: Vec<String>,
+  pub pattern: Option<Pattern>,
+  // bounds of numbers, inclusive
+  pub min: Option<f64>,
+  pub max: Option<f64>,
+  // in characters
+  pub max_length: Option<usize>,
+}
+
+impl FieldRules
+{
+  // Set the rule of one of RULE_KEYS, the error tells why its value
+  // is invalid
+  pub fn set(&mut self,
+             key: &str,
+             value: &Yaml)
+             -> Result<(), String>
+  {
+    let list = || String::from("expected a list of values");
+    match (key, value) {
+      ("required", Yaml::Boolean(required)) => {
+        self.required = *required
+      }
+      ("enum", Yaml::Array(values)) => {
+        for value in values {
+          self.one_of.push(scalar_text(value).ok_or_else(list)?);
+        }
+      }
+      ("regex", Yaml::String(source)) => {
+        self.pattern = Some(Pattern::new(source)?)
+      }
+      ("min", value) => self.min = Some(number(value)?),
+      ("max", value) => self.max = Some(number(value)?),
+      ("max_length", Yaml::Integer(n)) if *n >= 0 => {
+        self.max_length = Some(*n as usize)
+      }
+      ("required", _) => {
+        return Err("expected true or false".into())
+      }
+      ("enum", _) => return Err(list()),
+      ("regex", _) => return Err("expected a regex".into()),
+      ("max_length", _) => {
+        return Err("expected a positive integer".into())
+      }
+      _ => return Err(format!("unknown rule {}", key)),
+    }
+    Ok(())
+  }
+
+  // Why the cell breaks the rules, None for a missing column
+  pub fn check(&self, cell: Option<&Cell>) -> Vec<String>
+  {
+    let mut broken = vec![];
+    let cell = match cell {
+      Some(cell) if !cell.is_empty() && !cell.is_error() => cell,
+      _ => {
+        if self.required {
+          broken.push(String::from("is required"));
+        }
+        return broken;
+      }
+    };
+    let value = cell.to_string();
+    let value = value.trim();
+    let listed = self.one_of.iter().any(|v| v == value);
+    if !self.one_of.is_empty() && !listed {
+      broken.push(format!("'{}' is not one of {}",
+                          value,
+                          self.one_of.join(", ")));
+    }
+    if let Some(pattern) = self.pattern.as_ref() {
+      if !pattern.is_match(value) {
+        broken.push(format!("'{}' does not match {}",
+                            value,
+                            pattern.as_str()));
+      }
+    }
+    if self.min.is_some() || self.max.is_some() {
+      match value.parse::<f64>() {
+        Ok(x) if self.min.is_some_and(|min| x < min) => {
+          broken.push(format!("{} is below the minimum {}",
+                              value,
+                              self.min.unwrap_or_default()));
+        }
+        Ok(x) if self.max.is_some_and(|max| x > max) => {
+          broken.push(format!("{} is above the maximum {}",
+                              value,
+                              self.max.unwrap_or_default()));
+        }
+        Ok(_) => {}
+        Err(_) => broken.push(format!("'{}' is no number", value)),
+      }
+    }
+    if let Some(max_length) = self.max_length {
+      if value.chars().count() > max_length {
+        broken.push(format!("'{}' is longer than {} characters",
+                            value, max_length));
+      }
+    }
+    broken
+  }
+}
+
+// Rules broken by the cells of a row, after fallbacks and defaults,
+// a required field missing from the sheet breaks its rule in every row
+pub fn check_row(row: &[Cell],
+                 fields: &[&FieldSpec],
+                 field_map: &HashMap<String, usize>,
+                 fallbacks: &HashMap<String, Vec<usize>>)
+                 -> Vec<Db2MdError>
+{
+  let mut broken = vec![];
+  let lookup = row_lookup(row, fields, field_map, fallbacks);
+  for field in fields.iter() {
+    let computed;
+    let cell = match field.expr.as_ref() {
+      // md_gen reports expressions that fail
+      Some(expr) => {
+        computed = expr.eval(&lookup).unwrap_or_default();
+        Some(&computed)
+      }
+      None => {
+        let column = field_map.get(&field.path).copied();
+        let fallback = fallbacks.get(&field.path).map(Vec::as_slice);
+        field.pick(row, column, fallback.unwrap_or_default())
+      }
+    };
+    for message in field.rules.check(cell) {
+      broken.push(Db2MdError::Rule { field: field.path.clone(),
+                                     message });
+    }
+  }
+  broken
+}
+
+fn number(value: &Yaml) -> Result<f64, String>
+{
+  let number = match value {
+    Yaml::Integer(i) => Some(*i as f64),
+    Yaml::Real(_) => value.as_f64(),
+    _ => None,
+  };
+  number.ok_or_else(|| String::from("expected a number"))
+}
+
+// `enum: [1, open, true]` lists values as they are displayed
+fn scalar_text(value: &Yaml) -> Option<String>
+{
+  match value {
+    Yaml::String(s) | Yaml::Real(s) => Some(s.clone()),
+    Yaml::Integer(i) => Some(i.to_string()),
+    Yaml::Boolean(b) => Some(b.to_string()),
+    _ => None,
+  }
+}
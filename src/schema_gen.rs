@@ -0,0 +1,218 @@
+use std::collections::HashMap;
+use yaml_rust2::yaml::Hash;
+use yaml_rust2::{Yaml, YamlEmitter};
+
+use crate::cell::Cell;
+use crate::error::Db2MdError;
+use crate::field_type::FieldType;
+use crate::reader::HeaderLayout;
+use crate::yaml_parser::is_option_key;
+
+// Rows below the header read to infer the type of a column
+pub const SAMPLE_ROWS: usize = 100;
+
+// Starter schema with one field per column of the header, typed from
+// the first `sample_rows` rows below it. With `group`, columns sharing
+// a prefix such as `customer_name` and `customer_city`, or the parts
+// of dotted titles, are nested into sections
+pub fn infer_schema(rows: &[Vec<Cell>],
+                    layout: &HeaderLayout,
+                    sample_rows: usize,
+                    group: bool)
+                    -> Result<Yaml, Db2MdError>
+{
+  if rows.len() <= layout.row {
+    return Err(Db2MdError::Mapping("empty sheet".to_string()));
+  }
+  let headers = layout.headers(rows);
+  let samples =
+    (0..rows.len()).filter(|&idx| layout.is_table_row(idx, true))
+                   .take(sample_rows)
+                   .map(|idx| &rows[idx])
+                   .collect::<Vec<_>>();
+
+  let prefixes = count_prefixes(&headers);
+  let mut schema = Hash::new();
+  for (index, header) in headers.iter().enumerate() {
+    let kind =
+      infer_type(samples.iter().filter_map(|row| row.get(index)));
+    let unique =
+      headers.iter().filter(|h| *h == header).count() == 1;
+    let path = if group {
+      field_path(header, &prefixes)
+    } else {
+      vec![header.clone()]
+    };
+    // the field finds its column by name only if it is the header
+    let column = if !unique {
+      Some(Yaml::Integer(index as i64))
+    } else if path.join(".") != *header {
+      Some(Yaml::String(header.clone()))
+    } else {
+      None
+    };
+    let field = field_node(kind, column);
+    if header.trim().is_empty()
+       || !insert_field(&mut schema, &path, field)
+    {
+      // no title, a title repeated or both a field and a section
+      let key = free_key(&schema, header, index);
+      let field =
+        field_node(kind, Some(Yaml::Integer(index as i64)));
+      schema.insert(Yaml::String(key), field);
+    }
+  }
+  Ok(Yaml::Hash(schema))
+}
+
+// Type shared by all sample cells, text when they disagree
+pub fn infer_type<'a>(cells: impl Iterator<Item = &'a Cell>)
+                      -> FieldType
+{
+  let mut kind = None;
+  for cell in cells.filter(|c| !c.is_empty() && !c.is_error()) {
+    let cell_kind = match cell {
+      Cell::Int(_) | Cell::Float(_) => FieldType::Number,
+      Cell::Date(_) => FieldType::Date,
+      Cell::DateTime(_) => FieldType::DateTime,
+      Cell::Time(_) => FieldType::Time,
+      Cell::Duration(_) => FieldType::Duration,
+      Cell::Bool(_) => FieldType::Bool,
+      _ => return FieldType::Text,
+    };
+    kind = match (kind, cell_kind) {
+      (None, k) => Some(k),
+      (Some(a), b) if a == b => Some(a),
+      // dates with and without a time of day
+      (Some(FieldType::Date | FieldType::DateTime),
+       FieldType::Date | FieldType::DateTime) => {
+        Some(FieldType::DateTime)
+      }
+      _ => return FieldType::Text,
+    };
+  }
+  kind.unwrap_or_default()
+}
+
+// The schema as YAML text that parse_yaml_schema reads back
+pub fn schema_to_string(schema: &Yaml)
+                        -> Result<String, Db2MdError>
+{
+  let mut out = String::new();
+  YamlEmitter::new(&mut out).dump(schema).map_err(|e| {
+                                           Db2MdError::Schema(e.to_string())
+                                         })?;
+  let body = out.strip_prefix("---\n").unwrap_or(&out);
+  Ok(format!("{}\n", body))
+}
+
+// Part of a title before the first space, `_` or `-`
+fn title_prefix(header: &str) -> Option<(&str, &str)>
+{
+  let (prefix, rest) = header.split_once([' ', '_', '-'])?;
+  let rest = rest.trim_start_matches([' ', '_', '-']);
+  (!prefix.is_empty() && !rest.is_empty()).then_some((prefix, rest))
+}
+
+// A section holding only keys such as `type` and `source` reads as the
+// options of a field, so those names are never grouped
+fn group_prefix(header: &str) -> Option<(&str, &str)>
+{
+  title_prefix(header).filter(|(_, rest)| !is_option_key(rest))
+}
+
+fn count_prefixes(headers: &[String]) -> HashMap<String, usize>
+{
+  let mut prefixes = HashMap::new();
+  for header in headers.iter().filter(|h| !h.contains('.')) {
+    if let Some((prefix, _)) = group_prefix(header) {
+      *prefixes.entry(prefix.to_string()).or_insert(0) += 1;
+    }
+  }
+  prefixes
+}
+
+// Keys from the top section down to the field
+fn field_path(header: &str,
+              prefixes: &HashMap<String, usize>)
+              -> Vec<String>
+{
+  // numbers such as `3.12` are no dotted titles
+  let parts = header.split('.').collect::<Vec<_>>();
+  if parts.len() > 1
+     && parts.iter().all(|p| !p.is_empty())
+     && !parts[1..].iter().any(|p| is_option_key(p))
+     && header.parse::<f64>().is_err()
+  {
+    return parts.into_iter().map(String::from).collect();
+  }
+  match group_prefix(header) {
+    Some((prefix, rest)) if prefixes.get(prefix) > Some(&1) => {
+      vec![prefix.to_string(), rest.to_string()]
+    }
+    _ => vec![header.to_string()],
+  }
+}
+
+// `price: number`, or `price: {column: "Unit Price", type: number}`
+fn field_node(kind: FieldType, column: Option<Yaml>) -> Yaml
+{
+  let kind = Yaml::String(kind.name().to_string());
+  match column {
+    Some(column) => {
+      let mut hash = Hash::new();
+      hash.insert(Yaml::String("column".to_string()), column);
+      hash.insert(Yaml::String("type".to_string()), kind);
+      Yaml::Hash(hash)
+    }
+    None => kind,
+  }
+}
+
+// False if the path is taken or runs through a field
+fn insert_field(schema: &mut Hash,
+                path: &[String],
+                field: Yaml)
+                -> bool
+{
+  let Some((key, rest)) = path.split_first() else {
+    return false;
+  };
+  let key = Yaml::String(key.clone());
+  if rest.is_empty() {
+    if schema.contains_key(&key) {
+      return false;
+    }
+    schema.insert(key, field);
+    return true;
+  }
+  let section =
+    schema.entry(key).or_insert_with(|| Yaml::Hash(Hash::new()));
+  match section {
+    // fields written as a hash carry a `column`
+    Yaml::Hash(hash)
+      if !hash.contains_key(&Yaml::String("column".to_string())) =>
+    {
+      insert_field(hash, rest, field)
+    }
+    _ => false,
+  }
+}
+
+// The title, or `column_3` for an empty one, followed by a number if
+// needed to make it unique at the top of the schema
+fn free_key(schema: &Hash, header: &str, index: usize) -> String
+{
+  let base = if header.trim().is_empty() {
+    format!("column_{}", index)
+  } else {
+    header.to_string()
+  };
+  let mut key = base.clone();
+  let mut n = 2;
+  while schema.contains_key(&Yaml::String(key.clone())) {
+    key = format!("{}_{}", base, n);
+    n += 1;
+  }
+  key
+}
@@ -0,0 +1,498 @@
+use serde::Serialize;
+use std::collections::HashMap;
+use std::fmt;
+use yaml_rust2::parser::{Event, MarkedEventReceiver, Parser};
+use yaml_rust2::scanner::Marker;
+use yaml_rust2::yaml::Hash;
+use yaml_rust2::{Yaml, YamlLoader};
+
+use crate::error::Db2MdError;
+use crate::expr::Expr;
+use crate::field_type::FieldType;
+use crate::rules::{FieldRules, RulePolicy, RULE_KEYS};
+use crate::yaml_parser::{
+  is_field_options, is_option_key, FIELD_OPTION_KEYS, OPTIONS_KEY,
+  SCHEMA_OPTION_KEYS,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LintLevel
+{
+  // the schema does not say what its author meant
+  Error,
+  // converts, but maybe not as expected
+  Warning,
+}
+
+// A problem found in a schema, at a one-based line and column when it
+// belongs to a key
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct LintIssue
+{
+  pub level: LintLevel,
+  pub position: Option<(usize, usize)>,
+  pub message: String,
+}
+
+impl LintIssue
+{
+  pub fn is_error(&self) -> bool
+  {
+    self.level == LintLevel::Error
+  }
+}
+
+impl fmt::Display for LintIssue
+{
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result
+  {
+    if let Some((line, col)) = self.position {
+      write!(f, "{}:{}: ", line, col)?;
+    }
+    let level = match self.level {
+      LintLevel::Error => "error",
+      LintLevel::Warning => "warning",
+    };
+    write!(f, "{}: {}", level, self.message)
+  }
+}
+
+pub fn lint_schema_file(file_path: &str)
+                        -> Result<Vec<LintIssue>, Db2MdError>
+{
+  let contents =
+    std::fs::read_to_string(file_path).map_err(|e| {
+                                        Db2MdError::io(file_path, e)
+                                      })?;
+  Ok(lint_schema(&contents))
+}
+
+// Everything parse_yaml_schema and extract_fields would accept
+// silently although it is most likely a mistake
+pub fn lint_schema(contents: &str) -> Vec<LintIssue>
+{
+  let docs = match YamlLoader::load_from_str(contents) {
+    Ok(docs) => docs,
+    Err(e) => {
+      let mark = e.marker();
+      return vec![error(Some((mark.line(), mark.col() + 1)),
+                        e.info())];
+    }
+  };
+  let mut linter = Linter { positions: key_positions(contents),
+                            issues: vec![],
+                            leaves: vec![],
+                            inputs: vec![],
+                            computed: vec![] };
+  match docs.first() {
+    None | Some(Yaml::Null) => {
+      linter.issues.push(error(None, "empty document"));
+    }
+    Some(Yaml::Hash(hash)) => {
+      linter.lint_section(hash, "");
+      linter.duplicate_leaves();
+      linter.expr_inputs();
+    }
+    Some(_) => {
+      let message =
+        "the schema is no mapping of sections and fields";
+      linter.issues.push(error(None, message));
+    }
+  }
+  linter.issues
+}
+
+// Columns of the sheet that no field is mapped to, not even as a
+// fallback
+pub fn unmapped_columns(field_map: &HashMap<String, usize>,
+                        fallbacks: &HashMap<String, Vec<usize>>,
+                        headers: &[String],
+                        col_number: usize)
+                        -> Vec<LintIssue>
+{
+  let mut issues = vec![];
+  for idx in 0..col_number {
+    if field_map.values().any(|&c| c == idx)
+       || fallbacks.values().any(|c| c.contains(&idx))
+    {
+      continue;
+    }
+    let message = match headers.get(idx) {
+      Some(h) if !h.trim().is_empty() => {
+        format!("column {} '{}' is used by no field", idx, h)
+      }
+      _ => format!("column {} is used by no field", idx),
+    };
+    issues.push(LintIssue { level: LintLevel::Warning,
+                            position: None,
+                            message });
+  }
+  issues
+}
+
+fn error(position: Option<(usize, usize)>,
+         message: &str)
+         -> LintIssue
+{
+  LintIssue { level: LintLevel::Error,
+              position,
+              message: message.to_string() }
+}
+
+struct Linter
+{
+  // of every key by its dotted path
+  positions: HashMap<String, (usize, usize)>,
+  issues: Vec<LintIssue>,
+  // fields by the last part of their path, unless bound to a column
+  leaves: Vec<(String, String)>,
+  // paths of the fields expressions can read, and the expressions
+  inputs: Vec<String>,
+  computed: Vec<(String, Expr)>,
+}
+
+impl Linter
+{
+  fn report(&mut self, level: LintLevel, path: &str, message: String)
+  {
+    let position = self.positions.get(path).copied();
+    self.issues.push(LintIssue { level, position, message });
+  }
+
+  fn lint_section(&mut self, hash: &Hash, prefix: &str)
+  {
+    for (key, value) in hash {
+      let path = join(prefix, &scalar_text(key));
+      // extract_fields leaves out the whole node
+      let Yaml::String(key) = key else {
+        let message =
+          format!("key {} is no text, quote it", scalar_text(key));
+        self.report(LintLevel::Error, &path, message);
+        continue;
+      };
+      if prefix.is_empty() && key == OPTIONS_KEY {
+        self.lint_options(value, &path, true);
+        continue;
+      }
+      self.lint_node(value, &path);
+    }
+  }
+
+  fn lint_node(&mut self, value: &Yaml, path: &str)
+  {
+    match value {
+      Yaml::String(kind) => {
+        self.lint_type(kind, path);
+        self.add_leaf(path);
+        self.inputs.push(path.to_string());
+      }
+      Yaml::Hash(hash) if is_field_options(hash) => {
+        self.lint_options(value, path, false);
+        let has =
+          |k: &str| hash.contains_key(&Yaml::String(k.into()));
+        if has("column") && has("source") {
+          self.report(LintLevel::Warning,
+                      path,
+                      format!("{} has both column and source, the \
+                               first source is taken",
+                              path));
+        }
+        if has("expr") {
+          return;
+        }
+        if !has("column") && !has("source") {
+          self.add_leaf(path);
+        }
+        self.inputs.push(path.to_string());
+      }
+      Yaml::Hash(hash) if hash.is_empty() => {
+        self.report(LintLevel::Warning,
+                    path,
+                    format!("section {} has no fields", path));
+      }
+      Yaml::Hash(hash) => {
+        // `{type: number, precison: 2}` reads as a section
+        let type_key = Yaml::String("type".to_string());
+        let has_rules = hash.keys()
+                            .filter_map(|k| k.as_str())
+                            .any(|k| RULE_KEYS.contains(&k));
+        if hash.contains_key(&type_key) {
+          let unknown = hash.keys()
+                            .filter_map(|k| k.as_str())
+                            .filter(|k| !is_option_key(k))
+                            .collect::<Vec<_>>();
+          self.report(LintLevel::Warning,
+                      path,
+                      format!("{} is read as a section because of \
+                               unknown options {:?}",
+                              path, unknown));
+        } else if has_rules {
+          // `{enum: [open, closed]}` too
+          self.report(LintLevel::Warning,
+                      path,
+                      format!("{} is read as a section, give its rules \
+                               a type",
+                              path));
+        }
+        self.lint_section(hash, path);
+      }
+      Yaml::Null => {
+        self.report(LintLevel::Error,
+                    path,
+                    format!("field {} has no type", path));
+      }
+      Yaml::Array(_) => {
+        self.report(LintLevel::Error,
+                    path,
+                    format!("{} is a list, which a schema does not \
+                             support",
+                            path));
+      }
+      _ => {
+        self.report(LintLevel::Error,
+                    path,
+                    format!("field {} has no type name but {}",
+                            path,
+                            scalar_text(value)));
+      }
+    }
+  }
+
+  fn lint_type(&mut self, kind: &str, path: &str)
+  {
+    if FieldType::from_name(kind).is_none() {
+      self.report(LintLevel::Error,
+                  path,
+                  format!("unknown type '{}' of {}, expected text, \
+                           number, date, datetime, time, duration or \
+                           bool",
+                          kind, path));
+    }
+  }
+
+  // A field written as a hash, or the schema-wide `_options`
+  fn lint_options(&mut self, value: &Yaml, path: &str, schema: bool)
+  {
+    let Yaml::Hash(hash) = value else {
+      self.report(LintLevel::Error,
+                  path,
+                  format!("{} is no mapping of options", path));
+      return;
+    };
+    for (key, value) in hash {
+      let name = key.as_str().unwrap_or_default();
+      let known = if schema {
+        !["column", "source", "default", "expr", "hidden"].contains(&name)
+        && (FIELD_OPTION_KEYS.contains(&name)
+            || SCHEMA_OPTION_KEYS.contains(&name))
+      } else {
+        is_option_key(name)
+      };
+      if !known {
+        self.report(LintLevel::Warning,
+                    path,
+                    format!("unknown option {} of {}",
+                            scalar_text(key),
+                            path));
+        continue;
+      }
+      let valid = match (name, value) {
+        ("type", Yaml::String(kind)) => {
+          self.lint_type(kind, path);
+          true
+        }
+        ("precision" | "column", Yaml::Integer(n)) => *n >= 0,
+        ("precision", _) => false,
+        ("source", Yaml::Array(sources)) => {
+          !sources.is_empty()
+          && sources.iter().all(|s| match s {
+                             Yaml::Integer(n) => *n >= 0,
+                             Yaml::String(_) => true,
+                             _ => false,
+                           })
+        }
+        ("source", _) => false,
+        ("hidden", value) => matches!(value, Yaml::Boolean(_)),
+        ("expr", Yaml::String(source)) => {
+          match Expr::parse(source) {
+            Ok(expr) => self.computed.push((path.to_string(), expr)),
+            Err(e) => {
+              self.report(LintLevel::Error,
+                          path,
+                          format!("invalid expr of {}: {}",
+                                  path, e));
+            }
+          }
+          true
+        }
+        ("expr", _) => false,
+        ("default", value) => {
+          matches!(value,
+                   Yaml::String(_)
+                   | Yaml::Integer(_)
+                   | Yaml::Real(_)
+                   | Yaml::Boolean(_))
+        }
+        ("on_invalid", Yaml::String(policy)) => {
+          RulePolicy::from_name(policy).is_some()
+        }
+        (rule, _) if RULE_KEYS.contains(&rule) => {
+          if let Err(e) = FieldRules::default().set(rule, value) {
+            self.report(LintLevel::Error,
+                        path,
+                        format!("invalid {} of {}: {}",
+                                rule, path, e));
+          }
+          true
+        }
+        (_, Yaml::String(_)) => true,
+        _ => false,
+      };
+      if !valid {
+        self.report(LintLevel::Error,
+                    path,
+                    format!("invalid {} of {}: {}",
+                            name,
+                            path,
+                            scalar_text(value)));
+      }
+    }
+  }
+
+  fn add_leaf(&mut self, path: &str)
+  {
+    let name = path.rsplit('.').next().unwrap_or(path);
+    self.leaves.push((name.to_lowercase(), path.to_string()));
+  }
+
+  // Fields with the same name match the same header
+  fn duplicate_leaves(&mut self)
+  {
+    let leaves = std::mem::take(&mut self.leaves);
+    for (idx, (name, path)) in leaves.iter().enumerate() {
+      let first = leaves[..idx].iter().find(|(n, _)| n == name);
+      if let Some((_, other)) = first {
+        self.report(LintLevel::Warning,
+                    path,
+                    format!("{} has the same name as {}, bind one of \
+                             them with `column:`",
+                            path, other));
+      }
+    }
+  }
+
+  // Expressions read fields with a column, not other expressions
+  fn expr_inputs(&mut self)
+  {
+    let computed = std::mem::take(&mut self.computed);
+    for (path, expr) in computed.iter() {
+      for input in expr.fields() {
+        if !self.inputs.iter().any(|p| p == input) {
+          self.report(LintLevel::Error,
+                      path,
+                      format!("expr of {} reads {}, which is no field \
+                               with a column",
+                              path, input));
+        }
+      }
+    }
+  }
+}
+
+fn scalar_text(value: &Yaml) -> String
+{
+  match value {
+    Yaml::String(s) | Yaml::Real(s) => s.clone(),
+    Yaml::Integer(i) => i.to_string(),
+    Yaml::Boolean(b) => b.to_string(),
+    Yaml::Null => String::from("null"),
+    Yaml::Array(_) => String::from("a list"),
+    Yaml::Hash(_) => String::from("a mapping"),
+    _ => String::from("an alias"),
+  }
+}
+
+// Line and column of every key of the first document, by dotted path
+fn key_positions(contents: &str) -> HashMap<String, (usize, usize)>
+{
+  let mut receiver =
+    KeyPositions { stack: vec![], positions: HashMap::new() };
+  let _ = Parser::new_from_str(contents).load(&mut receiver, false);
+  receiver.positions
+}
+
+enum Frame
+{
+  // path of the mapping and the key whose value comes next
+  Mapping
+  {
+    path: String,
+    key: Option<String>,
+  },
+  Sequence,
+}
+
+struct KeyPositions
+{
+  stack: Vec<Frame>,
+  positions: HashMap<String, (usize, usize)>,
+}
+
+impl KeyPositions
+{
+  fn child_path(&self) -> String
+  {
+    match self.stack.last() {
+      Some(Frame::Mapping { path, key: Some(key) }) => {
+        join(path, key)
+      }
+      _ => String::new(),
+    }
+  }
+
+  fn value_done(&mut self)
+  {
+    if let Some(Frame::Mapping { key, .. }) = self.stack.last_mut() {
+      *key = None;
+    }
+  }
+}
+
+impl MarkedEventReceiver for KeyPositions
+{
+  fn on_event(&mut self, ev: Event, mark: Marker)
+  {
+    match ev {
+      Event::MappingStart(..) => {
+        let path = self.child_path();
+        self.stack.push(Frame::Mapping { path, key: None });
+      }
+      Event::SequenceStart(..) => self.stack.push(Frame::Sequence),
+      Event::MappingEnd | Event::SequenceEnd => {
+        self.stack.pop();
+        self.value_done();
+      }
+      Event::Scalar(value, ..) => match self.stack.last_mut() {
+        Some(Frame::Mapping { path, key: key @ None }) => {
+          self.positions
+              .entry(join(path, &value))
+              .or_insert((mark.line(), mark.col() + 1));
+          *key = Some(value);
+        }
+        _ => self.value_done(),
+      },
+      Event::Alias(_) => self.value_done(),
+      _ => {}
+    }
+  }
+}
+
+fn join(prefix: &str, key: &str) -> String
+{
+  if prefix.is_empty() {
+    key.to_string()
+  } else {
+    format!("{}.{}", prefix, key)
+  }
+}
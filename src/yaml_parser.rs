@@ -1,14 +1,95 @@
-use std::collections::HashMap;
-use std::error::Error;
+use std::collections::{HashMap, HashSet};
 use std::fs::read_to_string;
+use yaml_rust2::yaml::Hash;
 use yaml_rust2::{Yaml, YamlLoader};
 
+use crate::cell::Cell;
+use crate::error::Db2MdError;
+use crate::expr::Expr;
+use crate::field_type::{EmptyPolicy, FieldFormat, FieldType};
+use crate::rules::{FieldRules, RulePolicy, RULE_KEYS};
+
+// Top-level key holding options for every field, not a section
+pub const OPTIONS_KEY: &str = "_options";
+
+// Keys of a field written as a hash, e.g.
+// `price: {type: number, precision: 2}`
+pub const FIELD_OPTION_KEYS: [&str; 10] = ["type",
+                                           "column",
+                                           "source",
+                                           "default",
+                                           "expr",
+                                           "hidden",
+                                           "precision",
+                                           "thousands",
+                                           "format",
+                                           "empty"];
+
+// Keys under `_options`, besides the field options that only make
+// sense for a single field
+pub const SCHEMA_OPTION_KEYS: [&str; 4] =
+  ["date_format", "datetime_format", "time_format", "on_invalid"];
+
+// Column a field is bound to with `column:`, instead of its position
+// or its name
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ColumnRef
+{
+  // zero-based, `column: 1`
+  Index(usize),
+  // an exact header, else a column letter such as `B`
+  Name(String),
+}
+
+impl ColumnRef
+{
+  fn resolve(&self, headers: &[String]) -> Option<usize>
+  {
+    let index = match self {
+      ColumnRef::Index(index) => Some(*index),
+      ColumnRef::Name(name) => {
+        headers.iter()
+               .position(|h| h == name)
+               .or_else(|| normalized_match(name, headers))
+               .or_else(|| column_letter_index(name))
+      }
+    };
+    // a binding past the last header is as invalid as a missing one
+    index.filter(|&index| {
+           headers.is_empty() || index < headers.len()
+         })
+  }
+}
+
+// `A` is 0, `Z` 25, `AA` 26, up to three upper case letters
+fn column_letter_index(name: &str) -> Option<usize>
+{
+  if name.is_empty()
+     || name.len() > 3
+     || !name.bytes().all(|b| b.is_ascii_uppercase())
+  {
+    return None;
+  }
+  let number =
+    name.bytes().fold(0, |n, b| n * 26 + (b - b'A') as usize + 1);
+  Some(number - 1)
+}
+
 pub fn parse_yaml_schema(file_path: &str)
-                         -> Result<Yaml, Box<dyn Error>>
+                         -> Result<Yaml, Db2MdError>
 {
-  let contents = read_to_string(file_path)?;
-  let docs = YamlLoader::load_from_str(&contents)?;
-  Ok(docs[0].clone())
+  let contents = read_to_string(file_path).map_err(|e| {
+                                            Db2MdError::io(file_path,
+                                                           e)
+                                          })?;
+  let docs = YamlLoader::load_from_str(&contents).map_err(|e| {
+               Db2MdError::Schema(format!("{}: {}", file_path, e))
+             })?;
+  docs.into_iter().next().ok_or_else(|| {
+                           Db2MdError::Schema(format!("{}: empty \
+                                                       document",
+                                                      file_path))
+                         })
 }
 
 // Recursively extracts field paths from the YAML schema.
@@ -17,9 +98,15 @@ pub fn extract_fields(yaml: &Yaml,
                       fields: &mut Vec<String>)
 {
   match yaml {
+    Yaml::Hash(hash) if is_field_options(hash) => {
+      fields.push(prefix.to_string());
+    }
     Yaml::Hash(hash) => {
       for (key, value) in hash {
         if let Yaml::String(key_str) = key {
+          if prefix.is_empty() && key_str == OPTIONS_KEY {
+            continue;
+          }
           let new_prefix = if prefix.is_empty() {
             key_str.clone()
           } else {
@@ -36,7 +123,545 @@ pub fn extract_fields(yaml: &Yaml,
   }
 }
 
-pub fn map_fields_to_columns(fields: &[String],
+// A hash made only of option and rule keys, with a `type`, a
+// `column`, a `source` or an `expr`, is a field rather than a section
+pub fn is_field_options(hash: &Hash) -> bool
+{
+  let has = |k: &&str| hash.contains_key(&Yaml::String(k.to_string()));
+  ["type", "column", "source", "expr"].iter().any(has)
+  && hash.keys().all(|k| k.as_str().is_some_and(is_option_key))
+}
+
+// Keys of a field written as a hash
+pub fn is_option_key(key: &str) -> bool
+{
+  FIELD_OPTION_KEYS.contains(&key) || RULE_KEYS.contains(&key)
+}
+
+// A schema as sections and fields in the order they are written, read
+// from YAML or built in code with add_field
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Schema
+{
+  // options under `_options`, inherited by the fields read from YAML
+  pub defaults: FieldFormat,
+  // rows breaking the rules of their fields
+  pub on_invalid: RulePolicy,
+  pub items: Vec<SchemaItem>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum SchemaItem
+{
+  Field(Box<FieldSpec>),
+  Section(Section),
+}
+
+// A heading grouping the fields and sections below it
+#[derive(Debug, Clone, PartialEq)]
+pub struct Section
+{
+  pub name: String,
+  // dotted, e.g. `customer.department`
+  pub path: String,
+  pub items: Vec<SchemaItem>,
+}
+
+// A field rendered from one column of a row
+#[derive(Debug, Clone, PartialEq)]
+pub struct FieldSpec
+{
+  pub name: String,
+  pub path: String,
+  // declared type and rendering options
+  pub format: FieldFormat,
+  // column bound with `column:` or first of `source:`, else found by
+  // order or header
+  pub column: Option<ColumnRef>,
+  // rest of `source:`, tried in order when the cell is empty
+  pub fallbacks: Vec<ColumnRef>,
+  // taken when every source is empty
+  pub default: Option<Cell>,
+  // computed from other fields instead of read from a column
+  pub expr: Option<Expr>,
+  // read and checked but not written, e.g. to be used by an expr
+  pub hidden: bool,
+  pub rules: FieldRules,
+}
+
+impl FieldSpec
+{
+  pub fn new(path: &str, kind: FieldType) -> Self
+  {
+    let name = path.rsplit('.').next().unwrap_or(path);
+    Self { name: name.to_string(),
+           path: path.to_string(),
+           format: FieldFormat { kind, ..FieldFormat::default() },
+           column: None,
+           fallbacks: vec![],
+           default: None,
+           expr: None,
+           hidden: false,
+           rules: FieldRules::default() }
+  }
+
+  pub fn kind(&self) -> FieldType
+  {
+    self.format.kind
+  }
+
+  pub fn with_format(mut self, format: FieldFormat) -> Self
+  {
+    self.format = format;
+    self
+  }
+
+  pub fn bound_to(mut self, column: ColumnRef) -> Self
+  {
+    self.column = Some(column);
+    self
+  }
+
+  pub fn with_rules(mut self, rules: FieldRules) -> Self
+  {
+    self.rules = rules;
+    self
+  }
+
+  pub fn or_from(mut self, column: ColumnRef) -> Self
+  {
+    self.fallbacks.push(column);
+    self
+  }
+
+  pub fn or_default(mut self, default: Cell) -> Self
+  {
+    self.default = Some(default);
+    self
+  }
+
+  pub fn computed(mut self, expr: Expr) -> Self
+  {
+    self.expr = Some(expr);
+    self
+  }
+
+  pub fn hidden(mut self) -> Self
+  {
+    self.hidden = true;
+    self
+  }
+
+  // The cell of `column`, or of the first of `fallbacks` that is
+  // neither empty nor an error, or the default, or what is left. None
+  // as `column` for a field missing from the sheet
+  pub fn pick<'a>(&'a self,
+                  row: &'a [Cell],
+                  column: Option<usize>,
+                  fallbacks: &[usize])
+                  -> Option<&'a Cell>
+  {
+    let filled = |c: &&Cell| !c.is_empty() && !c.is_error();
+    let found = column.iter()
+                      .chain(fallbacks)
+                      .filter_map(|&c| row.get(c))
+                      .find(filled);
+    found.or(self.default.as_ref())
+         .or_else(|| column.and_then(|c| row.get(c)))
+  }
+}
+
+impl Schema
+{
+  pub fn load(file_path: &str) -> Result<Self, Db2MdError>
+  {
+    Self::from_yaml(&parse_yaml_schema(file_path)?)
+  }
+
+  // Nodes that are neither a type name, a hash of field options nor
+  // a section are left out like extract_fields does, invalid rules
+  // fail
+  pub fn from_yaml(yaml: &Yaml) -> Result<Self, Db2MdError>
+  {
+    let defaults = match &yaml[OPTIONS_KEY] {
+      Yaml::Hash(hash) => {
+        apply_options(hash, FieldFormat::default())
+      }
+      _ => FieldFormat::default(),
+    };
+    let on_invalid = match &yaml[OPTIONS_KEY]["on_invalid"] {
+      Yaml::BadValue => RulePolicy::default(),
+      value => {
+        value.as_str().and_then(RulePolicy::from_name).ok_or_else(|| {
+          Db2MdError::Schema(String::from("on_invalid is none of skip, \
+                                           warn or fail"))
+        })?
+      }
+    };
+    let items = match yaml {
+      Yaml::Hash(hash) => read_items(hash, "", &defaults)?,
+      _ => vec![],
+    };
+    let schema = Self { defaults, on_invalid, items };
+    schema.check_exprs()?;
+    Ok(schema)
+  }
+
+  // Expressions read fields that have a column, so that they cannot
+  // depend on each other
+  pub fn check_exprs(&self) -> Result<(), Db2MdError>
+  {
+    let fields = self.fields();
+    for field in fields.iter() {
+      let Some(expr) = field.expr.as_ref() else {
+        continue;
+      };
+      for path in expr.fields() {
+        let input =
+          fields.iter().any(|f| f.path == path && f.expr.is_none());
+        if !input {
+          return Err(Db2MdError::Schema(format!("expr of {} reads {}, \
+                                                 which is no field with \
+                                                 a column",
+                                                field.path, path)));
+        }
+      }
+    }
+    Ok(())
+  }
+
+  // Append a field, creating the sections of its path that are missing
+  pub fn add_field(&mut self, field: FieldSpec)
+  {
+    let mut items = &mut self.items;
+    let mut path = String::new();
+    let parents = field.path.split('.').collect::<Vec<_>>();
+    for name in parents[..parents.len() - 1].iter() {
+      path = join_path(&path, name);
+      let found = items.iter().position(|item| {
+                    matches!(item, SchemaItem::Section(s) if s.path == path)
+                  });
+      let idx = match found {
+        Some(idx) => idx,
+        None => {
+          let section = Section { name: name.to_string(),
+                                  path: path.clone(),
+                                  items: vec![] };
+          items.push(SchemaItem::Section(section));
+          items.len() - 1
+        }
+      };
+      let SchemaItem::Section(section) = &mut items[idx] else {
+        unreachable!();
+      };
+      items = &mut section.items;
+    }
+    items.push(SchemaItem::Field(Box::new(field)));
+  }
+
+  // Every field in schema order
+  pub fn fields(&self) -> Vec<&FieldSpec>
+  {
+    let mut fields = vec![];
+    collect_fields(&self.items, &mut fields);
+    fields
+  }
+
+  pub fn field_paths(&self) -> Vec<String>
+  {
+    self.fields().iter().map(|f| f.path.clone()).collect()
+  }
+
+  pub fn field(&self, path: &str) -> Option<&FieldSpec>
+  {
+    self.fields().into_iter().find(|f| f.path == path)
+  }
+}
+
+fn read_items(hash: &Hash,
+              prefix: &str,
+              defaults: &FieldFormat)
+              -> Result<Vec<SchemaItem>, Db2MdError>
+{
+  let mut items = vec![];
+  for (key, value) in hash {
+    let Yaml::String(name) = key else {
+      continue;
+    };
+    if prefix.is_empty() && name == OPTIONS_KEY {
+      continue;
+    }
+    let path = join_path(prefix, name);
+    let item = match value {
+      Yaml::String(kind) => {
+        let format = FieldFormat { kind: FieldType::parse(kind),
+                                   ..defaults.clone() };
+        let field =
+          FieldSpec::new(&path, format.kind).with_format(format);
+        SchemaItem::Field(Box::new(field))
+      }
+      Yaml::Hash(options) if is_field_options(options) => {
+        let format = apply_options(options, defaults.clone());
+        let mut field =
+          FieldSpec::new(&path, format.kind).with_format(format);
+        field.column = column_ref(&value["column"]);
+        if let Some(sources) = read_sources(&value["source"], &path)?
+        {
+          let mut sources = sources.into_iter();
+          field.column = sources.next();
+          field.fallbacks = sources.collect();
+        }
+        field.default = default_cell(&value["default"]);
+        field.expr = read_expr(&value["expr"], &path)?;
+        field.hidden = value["hidden"].as_bool().unwrap_or_default();
+        field.rules = read_rules(options, &path)?;
+        SchemaItem::Field(Box::new(field))
+      }
+      Yaml::Hash(children) => {
+        SchemaItem::Section(Section { name: name.clone(),
+                                      items:
+                                        read_items(children, &path,
+                                                   defaults)?,
+                                      path })
+      }
+      _ => continue,
+    };
+    items.push(item);
+  }
+  Ok(items)
+}
+
+fn read_rules(hash: &Hash,
+              path: &str)
+              -> Result<FieldRules, Db2MdError>
+{
+  let mut rules = FieldRules::default();
+  for (key, value) in hash {
+    let Some(key) = key.as_str().filter(|k| RULE_KEYS.contains(k))
+    else {
+      continue;
+    };
+    rules.set(key, value).map_err(|e| {
+      Db2MdError::Schema(format!("invalid {} of {}: {}", key, path, e))
+    })?;
+  }
+  Ok(rules)
+}
+
+// `source: [Serial, SN, 3]`, None without a source
+fn read_sources(value: &Yaml,
+                path: &str)
+                -> Result<Option<Vec<ColumnRef>>, Db2MdError>
+{
+  let invalid = || {
+    Db2MdError::Schema(format!("invalid source of {}: expected a list \
+                                of columns",
+                               path))
+  };
+  match value {
+    Yaml::BadValue => Ok(None),
+    Yaml::Array(values) if !values.is_empty() => {
+      let sources =
+        values.iter().map(column_ref).collect::<Option<_>>();
+      sources.map(Some).ok_or_else(invalid)
+    }
+    _ => Err(invalid()),
+  }
+}
+
+fn read_expr(value: &Yaml,
+             path: &str)
+             -> Result<Option<Expr>, Db2MdError>
+{
+  match value {
+    Yaml::BadValue => Ok(None),
+    Yaml::String(source) => Expr::parse(source).map(Some).map_err(|e| {
+      Db2MdError::Schema(format!("invalid expr of {}: {}", path, e))
+    }),
+    _ => Err(Db2MdError::Schema(format!("invalid expr of {}: expected \
+                                         text",
+                                        path))),
+  }
+}
+
+// `default: 0` keeps the type YAML gives it
+fn default_cell(value: &Yaml) -> Option<Cell>
+{
+  match value {
+    Yaml::String(s) => Some(Cell::from(s.as_str())),
+    Yaml::Integer(i) => Some(Cell::Int(*i)),
+    Yaml::Real(_) => value.as_f64().map(Cell::Float),
+    Yaml::Boolean(b) => Some(Cell::Bool(*b)),
+    _ => None,
+  }
+}
+
+// `column: 1` or `column: "Unit Price"`
+fn column_ref(value: &Yaml) -> Option<ColumnRef>
+{
+  match value {
+    Yaml::Integer(index) if *index >= 0 => {
+      Some(ColumnRef::Index(*index as usize))
+    }
+    Yaml::String(name) => Some(ColumnRef::Name(name.clone())),
+    _ => None,
+  }
+}
+
+fn collect_fields<'a>(items: &'a [SchemaItem],
+                      fields: &mut Vec<&'a FieldSpec>)
+{
+  for item in items {
+    match item {
+      SchemaItem::Field(field) => fields.push(field),
+      SchemaItem::Section(section) => {
+        collect_fields(&section.items, fields)
+      }
+    }
+  }
+}
+
+fn join_path(prefix: &str, name: &str) -> String
+{
+  if prefix.is_empty() {
+    name.to_string()
+  } else {
+    format!("{}.{}", prefix, name)
+  }
+}
+
+fn apply_options(hash: &Hash, mut format: FieldFormat)
+                 -> FieldFormat
+{
+  for (key, value) in hash {
+    match (key.as_str(), value) {
+      (Some("type"), Yaml::String(kind)) => {
+        format.kind = FieldType::parse(kind)
+      }
+      (Some("precision"), Yaml::Integer(p)) if *p >= 0 => {
+        format.precision = Some(*p as usize)
+      }
+      (Some("thousands"), Yaml::String(sep)) => {
+        format.thousands = Some(sep.clone())
+      }
+      (Some("format"), Yaml::String(fmt)) => {
+        format.format = Some(fmt.clone())
+      }
+      (Some("empty"), Yaml::String(policy)) => {
+        format.empty = EmptyPolicy::parse(policy)
+      }
+      // defaults for every field, only under `_options`
+      (Some("date_format"), Yaml::String(fmt)) => {
+        format.date_format = fmt.clone()
+      }
+      (Some("datetime_format"), Yaml::String(fmt)) => {
+        format.datetime_format = fmt.clone()
+      }
+      (Some("time_format"), Yaml::String(fmt)) => {
+        format.time_format = fmt.clone()
+      }
+      _ => {}
+    }
+  }
+  format
+}
+
+// Header matching the end of a field path, `name` or
+// `customer.name` for `organization.customer.name`
+fn suffix_match(field: &str, headers: &[String]) -> Option<usize>
+{
+  headers.iter()
+         .enumerate()
+         .rev()
+         .filter(|(_, h)| {
+           !h.is_empty()
+           && field.strip_suffix(h.as_str())
+                   .is_some_and(|rest| rest.ends_with('.'))
+         })
+         .max_by_key(|(_, h)| h.len())
+         .map(|(index, _)| index)
+}
+
+// Compare headers and field names the way people write them, so
+// `unit-price` finds `Unit Price`, `UNIT_PRICE` or `Ｕｎｉｔ Ｐｒｉｃｅ`
+pub fn normalize_header(s: &str) -> String
+{
+  let mut normalized = String::new();
+  let mut separator = false;
+  for c in s.chars() {
+    // full-width forms of ASCII and the ideographic space
+    let c = match c {
+      '\u{ff01}'..='\u{ff5e}' => {
+        char::from_u32(c as u32 - 0xfee0).unwrap_or(c)
+      }
+      '\u{3000}' => ' ',
+      _ => c,
+    };
+    if c.is_whitespace() || c == '-' || c == '_' {
+      separator = !normalized.is_empty();
+      continue;
+    }
+    if separator && !normalized.ends_with('.') && c != '.' {
+      normalized.push(' ');
+    }
+    separator = false;
+    normalized.extend(c.to_lowercase());
+  }
+  normalized
+}
+
+// The whole field, else the end of its path, after normalizing both
+fn normalized_match(field: &str, headers: &[String])
+                    -> Option<usize>
+{
+  let field = normalize_header(field);
+  let headers =
+    headers.iter().map(|h| normalize_header(h)).collect::<Vec<_>>();
+  headers.iter()
+         .position(|h| *h == field)
+         .or_else(|| suffix_match(&field, &headers))
+}
+
+// Up to three headers closest to an invalid field, by edit distance
+// to the field or the last part of its path
+pub fn suggest_headers(field: &str,
+                       headers: &[String])
+                       -> Vec<String>
+{
+  let field = normalize_header(field);
+  let last = field.rsplit('.').next().unwrap_or(&field);
+  let max_distance = (last.chars().count() / 3).max(2);
+  let mut candidates = vec![];
+  for header in headers.iter().filter(|h| !h.trim().is_empty()) {
+    let normalized = normalize_header(header);
+    let distance = edit_distance(&field, &normalized)
+                     .min(edit_distance(last, &normalized));
+    if distance <= max_distance {
+      candidates.push((distance, header));
+    }
+  }
+  candidates.sort_by_key(|(distance, _)| *distance);
+  candidates.into_iter().take(3).map(|(_, h)| h.clone()).collect()
+}
+
+// Levenshtein distance counted in characters
+fn edit_distance(a: &str, b: &str) -> usize
+{
+  let b = b.chars().collect::<Vec<_>>();
+  let mut previous = (0..=b.len()).collect::<Vec<_>>();
+  for (i, ca) in a.chars().enumerate() {
+    let mut current = vec![i + 1];
+    for (j, cb) in b.iter().enumerate() {
+      let substitution = previous[j] + usize::from(ca != *cb);
+      current.push(substitution.min(previous[j + 1] + 1)
+                               .min(current[j] + 1));
+    }
+    previous = current;
+  }
+  previous[b.len()]
+}
+
+pub fn map_fields_to_columns(fields: &[&FieldSpec],
                              headers: &[String],
                              // fields not found in headers are
                              // invalid
@@ -44,21 +669,61 @@ pub fn map_fields_to_columns(fields: &[String],
                              -> HashMap<String, usize>
 {
   let mut mapping = HashMap::new();
-  if headers.is_empty() {
-    for (index, field) in fields.iter().enumerate() {
-      mapping.insert(field.clone(), index);
-    }
-  } else {
-    for field in fields {
-      let field_name = field.split('.').last().unwrap_or(field);
-      if let Some(index) =
-        headers.iter().position(|h| h == field_name)
-      {
-        mapping.insert(field.clone(), index);
-      } else {
-        invalid_fields.push(field.clone())
+  // a `source:` chain starts at the first column the sheet has
+  let bound_index = |field: &FieldSpec| {
+    let fallback =
+      || field.fallbacks.iter().find_map(|c| c.resolve(headers));
+    field.column.as_ref()?.resolve(headers).or_else(fallback)
+  };
+  // computed fields take no column
+  let inputs = fields.iter().filter(|f| f.expr.is_none());
+  // without a header, the other fields take the columns no field is
+  // bound to in order
+  let bound = inputs.clone()
+                    .filter_map(|f| bound_index(f))
+                    .collect::<HashSet<_>>();
+  let mut free = (0..).filter(|c| !bound.contains(c));
+  for field in inputs {
+    let path = &field.path;
+    // explicit bindings win over headers and field order
+    let index = match field.column.as_ref() {
+      Some(_) => bound_index(field),
+      None if headers.is_empty() => free.next(),
+      // a full dotted path, as in flattened JSON or merged header
+      // rows, wins over the longest trailing part of it such as the
+      // last segment, then the same after normalizing case and
+      // separators
+      None => headers.iter()
+                     .position(|h| h == path)
+                     .or_else(|| suffix_match(path, headers))
+                     .or_else(|| normalized_match(path, headers)),
+    };
+    match index {
+      Some(index) => {
+        mapping.insert(path.clone(), index);
       }
+      // the default stands in for a field missing from the sheet
+      None if field.default.is_some() => {}
+      None => invalid_fields.push(path.clone()),
     }
   }
   mapping
 }
+
+// Columns of the fallbacks of every field that has some, those missing
+// from the sheet are left out as a source may exist only in some of
+// them
+pub fn map_fallback_columns(fields: &[&FieldSpec],
+                            headers: &[String])
+                            -> HashMap<String, Vec<usize>>
+{
+  let mut mapping = HashMap::new();
+  for field in fields.iter().filter(|f| !f.fallbacks.is_empty()) {
+    let columns = field.fallbacks
+                       .iter()
+                       .filter_map(|c| c.resolve(headers))
+                       .collect();
+    mapping.insert(field.path.clone(), columns);
+  }
+  mapping
+}
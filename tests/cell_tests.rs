@@ -0,0 +1,41 @@
+#[cfg(test)]
+mod tests
+{
+  use chrono::NaiveDate;
+  use db2md::cell::*;
+
+  #[test]
+  fn test_format_float()
+  {
+    assert_eq!(format_float(0.1 + 0.2), "0.3");
+    assert_eq!(format_float(1e-7), "0.0000001");
+    assert_eq!(format_float(1.13), "1.13");
+    assert_eq!(format_float(312.0), "312");
+    assert_eq!(format_float(-2.5), "-2.5");
+    assert_eq!(format_float(123456789.123), "123456789.123");
+  }
+
+  #[test]
+  fn test_cell_from_datetime()
+  {
+    let date = NaiveDate::from_ymd_opt(2024, 8, 29).unwrap();
+    let midnight = date.and_hms_opt(0, 0, 0).unwrap();
+    assert_eq!(Cell::from_datetime(midnight), Cell::Date(date));
+    let noon = date.and_hms_opt(12, 30, 0).unwrap();
+    assert_eq!(Cell::from_datetime(noon).to_string(),
+               "2024-08-29 12:30:00");
+    assert!(Cell::from("  ").is_empty());
+    assert!(Cell::from("1.5").is_number());
+  }
+
+  #[test]
+  fn test_iso_duration()
+  {
+    let duration = parse_iso_duration("PT27H5M0S").unwrap();
+    assert_eq!(format_duration(duration), "27:05:00");
+    let duration = parse_iso_duration("-P1DT0.5H").unwrap();
+    assert_eq!(format_duration(duration), "-24:30:00");
+    assert!(parse_iso_duration("P1Y").is_none());
+    assert!(parse_iso_duration("10:30").is_none());
+  }
+}
@@ -0,0 +1,156 @@
+#[cfg(test)]
+mod tests
+{
+  use clap::Parser;
+  use db2md::cli::*;
+  use std::process::ExitCode;
+
+  fn run_args(args: &[&str]) -> ExitCode
+  {
+    let cli = Cli::try_parse_from(args).expect("invalid arguments");
+    run(cli.command.expect("missing subcommand"))
+  }
+
+  #[test]
+  fn test_no_subcommand_means_gui()
+  {
+    let cli = Cli::try_parse_from(["db2md"]).unwrap();
+    assert!(cli.command.is_none());
+  }
+
+  #[test]
+  fn test_convert()
+  {
+    let out = std::env::temp_dir().join("db2md-cli-convert");
+    let out = out.to_string_lossy();
+    let code = run_args(&["db2md",
+                          "convert",
+                          "--xlsx",
+                          "./tests/fruit_test.xlsx",
+                          "--schema",
+                          "./tests/schema.yaml",
+                          "--out",
+                          &out,
+                          "--prefix",
+                          "fruit"]);
+    assert_eq!(code, ExitCode::SUCCESS);
+    assert!(std::path::Path::new(&format!("{}/fruit-000.md", out)).exists());
+  }
+
+  #[test]
+  fn test_validate()
+  {
+    let code = run_args(&["db2md",
+                          "validate",
+                          "--xlsx",
+                          "./tests/fruit_test.xlsx",
+                          "--schema",
+                          "./tests/schema.yaml"]);
+    assert_eq!(code, ExitCode::SUCCESS);
+    // no field of the schema matches the first row as header
+    let code = run_args(&["db2md",
+                          "validate",
+                          "--xlsx",
+                          "./tests/fruit_test.xlsx",
+                          "--schema",
+                          "./tests/schema.yaml",
+                          "--header"]);
+    assert_eq!(code, ExitCode::FAILURE);
+  }
+
+  #[test]
+  fn test_validate_sqlite()
+  {
+    // column names of the table map fields without --header
+    let code = run_args(&["db2md",
+                          "validate",
+                          "--input",
+                          "./tests/ccms_test.db",
+                          "--sheet",
+                          "complaints",
+                          "--schema",
+                          "./tests/records_schema.yaml"]);
+    assert_eq!(code, ExitCode::SUCCESS);
+  }
+
+  #[test]
+  fn test_convert_header_layout()
+  {
+    let layout = ["--header-row",
+                  "3",
+                  "--header-rows",
+                  "2",
+                  "--skip-rows",
+                  "1"];
+    let mut args = vec!["db2md",
+                        "validate",
+                        "--xlsx",
+                        "./tests/banner_test.xlsx",
+                        "--schema",
+                        "./tests/banner_schema.yaml"];
+    args.extend(layout);
+    assert_eq!(run_args(&args), ExitCode::SUCCESS);
+
+    let out = std::env::temp_dir().join("db2md-cli-header-layout");
+    let out = out.to_string_lossy();
+    let _ = std::fs::remove_dir_all(&*out);
+    args[1] = "convert";
+    args.extend(["--out", &out, "--prefix", "complaint"]);
+    assert_eq!(run_args(&args), ExitCode::SUCCESS);
+    // files are numbered from the first data row
+    let doc =
+      std::fs::read_to_string(format!("{}/complaint-000.md", out)).unwrap();
+    assert!(doc.contains("## department\n\nIT\n"));
+    assert!(doc.contains("## price\n\n12.5\n"));
+    // banner, header and skipped rows are left out
+    let extra = format!("{}/complaint-002.md", out);
+    assert!(!std::path::Path::new(&extra).exists());
+  }
+
+  #[test]
+  fn test_init_schema()
+  {
+    let out = std::env::temp_dir().join("db2md-init-schema.yaml");
+    let out = out.to_string_lossy();
+    let _ = std::fs::remove_file(&*out);
+    let mut args = vec!["db2md",
+                        "init-schema",
+                        "--input",
+                        "./tests/records_test.json",
+                        "--group",
+                        "--out",
+                        &out];
+    assert_eq!(run_args(&args), ExitCode::SUCCESS);
+    let schema = std::fs::read_to_string(&*out).unwrap();
+    assert!(schema.contains("customer:\n  name: text\n"));
+    // an existing schema is kept without --force
+    assert_eq!(run_args(&args), ExitCode::FAILURE);
+    args.push("--force");
+    assert_eq!(run_args(&args), ExitCode::SUCCESS);
+
+    let code = run_args(&["db2md",
+                          "validate",
+                          "--input",
+                          "./tests/records_test.json",
+                          "--schema",
+                          &out]);
+    assert_eq!(code, ExitCode::SUCCESS);
+  }
+
+  #[test]
+  fn test_validate_lints_schema()
+  {
+    // every field maps to a column, but `txt` is no type
+    let schema = std::env::temp_dir().join("db2md-lint-schema.yaml");
+    std::fs::write(&schema,
+                   "organization:\n  sbu: txt\n  product: text\n")
+      .unwrap();
+    let code = run_args(&["db2md",
+                          "validate",
+                          "--xlsx",
+                          "./tests/fruit_test.xlsx",
+                          "--schema",
+                          &schema.to_string_lossy()]);
+    assert_eq!(code, ExitCode::FAILURE);
+  }
+}
@@ -0,0 +1,84 @@
+#[cfg(test)]
+mod tests
+{
+  use chrono::NaiveDate;
+  use db2md::expr::*;
+  use db2md::Cell;
+
+  fn eval(source: &str) -> Result<Cell, String>
+  {
+    let date = NaiveDate::from_ymd_opt(1990, 6, 15).unwrap();
+    let lookup = |path: &str| match path {
+      "first" => Some(Cell::from("Ada")),
+      "last" => Some(Cell::from("Lovelace")),
+      "quantity" => Some(Cell::Int(3)),
+      "price" => Some(Cell::Float(2.5)),
+      "birth" => Some(Cell::Date(date)),
+      "status code" => Some(Cell::from("C")),
+      "not" => Some(Cell::from("noted")),
+      "note" => Some(Cell::Empty),
+      "broken" => Some(Cell::Error("#DIV/0!".to_string())),
+      _ => None,
+    };
+    Expr::parse(source)?.eval(&lookup)
+  }
+
+  #[test]
+  fn test_eval_expr()
+  {
+    assert_eq!(eval("first & ' ' & last"),
+               Ok(Cell::from("Ada Lovelace")));
+    assert_eq!(eval("quantity * price"), Ok(Cell::Float(7.5)));
+    assert_eq!(eval("quantity * 2 + 1"), Ok(Cell::Int(7)));
+    assert_eq!(eval("-(quantity - 5) % 3"), Ok(Cell::Int(2)));
+    assert_eq!(eval("round(price / 3, 2)"), Ok(Cell::Float(0.83)));
+    assert_eq!(eval("years(birth, '2024-06-14')"), Ok(Cell::Int(33)));
+    assert_eq!(eval("days(birth, '1990-07-01')"), Ok(Cell::Int(16)));
+    assert_eq!(eval("if(quantity >= 3 and not false, 'bulk', 'single')"),
+               Ok(Cell::from("bulk")));
+    assert_eq!(eval("lookup(`status code`, 'O', 'Open', 'C', 'Closed', \
+                     'Unknown')"),
+               Ok(Cell::from("Closed")));
+    assert_eq!(eval("lookup('X', 'O', 'Open', 'Unknown')"),
+               Ok(Cell::from("Unknown")));
+    assert_eq!(eval("coalesce(note, upper(last))"),
+               Ok(Cell::from("LOVELACE")));
+    assert_eq!(eval("len(trim(' ab ')) == 2"), Ok(Cell::Bool(true)));
+    // keywords between backticks are names
+    assert_eq!(eval("`not` & '!'"), Ok(Cell::from("noted!")));
+    assert_eq!(eval("not `not` == 'x'"), Ok(Cell::Bool(true)));
+    // only the branch taken is evaluated
+    assert_eq!(eval("if(true, 1, 1 / 0)"), Ok(Cell::Int(1)));
+  }
+
+  #[test]
+  fn test_eval_expr_errors()
+  {
+    assert_eq!(eval("quantity / 0"),
+               Err("division by zero".to_string()));
+    assert_eq!(eval("note + 1"),
+               Err("an empty value is no number, use coalesce"
+                   .to_string()));
+    assert_eq!(eval("first * 2"), Err("'Ada' is no number".to_string()));
+    assert_eq!(eval("broken & 'x'"),
+               Err("a field holds #DIV/0!".to_string()));
+    assert_eq!(eval("missing"), Err("unknown field missing".to_string()));
+  }
+
+  #[test]
+  fn test_parse_expr()
+  {
+    let expr = Expr::parse("concat(a.first, ' ', `last name`)").unwrap();
+    assert_eq!(expr.fields(), vec!["a.first", "last name"]);
+    assert_eq!(expr.as_str(), "concat(a.first, ' ', `last name`)");
+    let error = |source: &str| Expr::parse(source).unwrap_err();
+    assert_eq!(error("1 +"), "unexpected end");
+    assert_eq!(error("(1"), "expected ')' at the end");
+    assert_eq!(error("a b"), "unexpected 'b'");
+    assert_eq!(error("'open"), "unclosed '");
+    assert_eq!(error("a $ b"), "unexpected '$'");
+    assert_eq!(error("system('rm')"), "unknown function system");
+    assert_eq!(error("round()"), "round takes 1 to 2 arguments, not 0");
+    assert_eq!(error(&"(".repeat(100)), "expression nested too deeply");
+  }
+}
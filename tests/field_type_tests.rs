@@ -0,0 +1,112 @@
+#[cfg(test)]
+mod tests
+{
+  use chrono::NaiveDate;
+  use db2md::field_type::*;
+  use db2md::Cell;
+
+  #[test]
+  fn test_render_number()
+  {
+    let format = FieldFormat { kind: FieldType::Number,
+                               precision: Some(2),
+                               thousands: Some(",".to_string()),
+                               ..FieldFormat::default() };
+    assert_eq!(format.render(&Cell::Float(1234567.456)).unwrap(),
+               "1,234,567.46");
+    assert_eq!(format.render(&Cell::from(" -980 ")).unwrap(),
+               "-980.00");
+    assert!(format.render(&Cell::from("n/a")).is_err());
+    // empty cells fit every type
+    assert_eq!(format.render(&Cell::Empty).unwrap(), "");
+
+    let plain = FieldFormat { kind: FieldType::Number,
+                              ..FieldFormat::default() };
+    assert_eq!(plain.render(&Cell::Float(0.1 + 0.2)).unwrap(),
+               "0.3");
+    // integers beyond 2^53 keep every digit
+    assert_eq!(plain.render(&Cell::Int(12345678901234567)).unwrap(),
+               "12345678901234567");
+    let grouped = FieldFormat { thousands: Some(",".to_string()),
+                                ..plain };
+    assert_eq!(grouped.render(&Cell::from("12345678901234567"))
+                      .unwrap(),
+               "12,345,678,901,234,567");
+  }
+
+  #[test]
+  fn test_render_date_and_bool()
+  {
+    let date = FieldFormat { kind: FieldType::parse("date"),
+                             ..FieldFormat::default() };
+    // spreadsheet serial number
+    assert_eq!(date.render(&Cell::Float(45533.0)).unwrap(),
+               "2024-08-29");
+    assert_eq!(date.render(&Cell::from("2024/08/29")).unwrap(),
+               "2024-08-29");
+    let day = NaiveDate::from_ymd_opt(2024, 8, 29).unwrap();
+    let noon = day.and_hms_opt(12, 0, 0).unwrap();
+    assert_eq!(date.render(&Cell::DateTime(noon)).unwrap(),
+               "2024-08-29");
+    assert!(date.render(&Cell::from("someday")).is_err());
+
+    let boolean = FieldFormat { kind: FieldType::parse("bool"),
+                                ..FieldFormat::default() };
+    assert_eq!(boolean.render(&Cell::Bool(true)).unwrap(), "Yes");
+    assert_eq!(boolean.render(&Cell::from("false")).unwrap(), "No");
+    assert!(boolean.render(&Cell::Float(0.5)).is_err());
+
+    // unknown types are text
+    assert_eq!(FieldType::parse("currency"), FieldType::Text);
+  }
+
+  #[test]
+  fn test_render_date_formats()
+  {
+    let day = NaiveDate::from_ymd_opt(2024, 8, 29).unwrap();
+    let opened = day.and_hms_opt(14, 5, 0).unwrap();
+    let chinese = FieldFormat { format:
+                                  Some("%Y年%m月%d日".to_string()),
+                                ..FieldFormat::default() };
+    assert_eq!(chinese.render(&Cell::Date(day)).unwrap(),
+               "2024年08月29日");
+
+    // text fields use the format of the cell's kind
+    let text = FieldFormat { datetime_format:
+                               "%Y-%m-%d %H:%M".to_string(),
+                             ..FieldFormat::default() };
+    assert_eq!(text.render(&Cell::DateTime(opened)).unwrap(),
+               "2024-08-29 14:05");
+    assert_eq!(text.render(&Cell::Date(day)).unwrap(), "2024-08-29");
+
+    let datetime = FieldFormat { kind:
+                                   FieldType::parse("datetime"),
+                                 ..text.clone() };
+    assert_eq!(datetime.render(&Cell::from("2024-08-29 14:05:00"))
+                       .unwrap(),
+               "2024-08-29 14:05");
+
+    let time = FieldFormat { kind: FieldType::parse("time"),
+                             format: Some("%H:%M".to_string()),
+                             ..FieldFormat::default() };
+    assert_eq!(time.render(&Cell::DateTime(opened)).unwrap(),
+               "14:05");
+
+    let duration = FieldFormat { kind:
+                                   FieldType::parse("duration"),
+                                 ..FieldFormat::default() };
+    assert_eq!(duration.render(&Cell::from("PT1H30M")).unwrap(),
+               "1:30:00");
+    assert_eq!(duration.render(&Cell::Float(1.25)).unwrap(),
+               "30:00:00");
+    // out of range durations do not fit instead of panicking
+    assert!(duration.render(&Cell::from("99999999999999:00")).is_err());
+    assert!(duration.render(&Cell::Float(-1e300)).is_err());
+    assert!(duration.render(&Cell::from("P1e300D")).is_err());
+
+    // a bad format is an error, not a panic
+    let bad = FieldFormat { format: Some("%Q".to_string()),
+                            ..FieldFormat::default() };
+    assert!(bad.render(&Cell::Date(day)).is_err());
+  }
+}
@@ -1,9 +1,27 @@
 #[cfg(test)]
 mod tests
 {
+  use chrono::NaiveDate;
+  use db2md::expr::Expr;
   use db2md::md_gen::*;
+  use db2md::{
+    Cell, Db2MdError, EmptyPolicy, FieldFormat, FieldSpec, FieldType,
+  };
   use std::collections::HashMap;
 
+  // text fields in schema order
+  fn fields(paths: &[&str]) -> Vec<FieldSpec>
+  {
+    paths.iter()
+         .map(|p| FieldSpec::new(p, FieldType::Text))
+         .collect()
+  }
+
+  fn refs(fields: &[FieldSpec]) -> Vec<&FieldSpec>
+  {
+    fields.iter().collect()
+  }
+
   #[test]
   fn test_generate_markdown()
   {
@@ -13,18 +31,202 @@ mod tests
     fields_map.insert("date".to_string(), 2usize);
     fields_map.insert("customer.price".to_string(), 3usize);
     fields_map.insert("customer.price.origin".to_string(), 4usize);
-    let data_row: Vec<String> = vec!["Apple".to_string(),
-           "red".to_string(),
-           "2024-10-11".to_string(),
-           "$3.14".to_string(),
-           "Produced in California".to_string(),];
+    let date = NaiveDate::from_ymd_opt(2024, 10, 11).unwrap();
+    let data_row: Vec<Cell> = vec![Cell::from("Apple"),
+           Cell::from("red"),
+           Cell::Date(date),
+           Cell::from("$3.14"),
+           Cell::from("Produced in California"),];
     let mut output = String::new();
     let expected_output =
       String::from("# product\n\n## name\n\nApple\n\n## \
                     color\n\nred\n\n# date\n\n2024-10-11\n\n# \
                     customer\n\n## price\n\n$3.14\n\n### \
                     origin\n\nProduced in California\n\n");
-    generate_markdown(&data_row, &fields_map, &mut output);
+    generate_markdown(&data_row,
+                      &refs(&fields(&["product.name",
+                                      "product.color",
+                                      "date",
+                                      "customer.price",
+                                      "customer.price.origin"])),
+                      &fields_map,
+                      &HashMap::new(),
+                      &mut output,
+                      &mut vec![]).unwrap();
     assert_eq!(output, expected_output);
   }
+
+  #[test]
+  fn test_generate_markdown_typed_cells()
+  {
+    let mut fields_map: HashMap<String, usize> = HashMap::new();
+    fields_map.insert("price".to_string(), 0usize);
+    fields_map.insert("weight".to_string(), 1usize);
+    fields_map.insert("ratio".to_string(), 2usize);
+    fields_map.insert("organic".to_string(), 3usize);
+    let data_row = vec![Cell::Float(0.1 + 0.2),
+                        Cell::Float(1e-7),
+                        Cell::Error("#DIV/0!".to_string()),
+                        Cell::Bool(true)];
+    let mut output = String::new();
+    let mut warnings = vec![];
+    generate_markdown(&data_row,
+                      &refs(&fields(&["price", "weight",
+                                      "ratio", "organic"])),
+                      &fields_map,
+                      &HashMap::new(),
+                      &mut output,
+                      &mut warnings).unwrap();
+    // error cells are blank and reported
+    assert_eq!(warnings,
+               vec![Db2MdError::ErrorCell { field:
+                                              "ratio".to_string(),
+                                            value:
+                                              "#DIV/0!".to_string() }]);
+    assert_eq!(output,
+               "# price\n\n0.3\n\n# weight\n\n0.0000001\n\n# \
+                ratio\n\n\n\n# organic\n\ntrue\n\n");
+  }
+
+  #[test]
+  fn test_generate_markdown_empty_policy()
+  {
+    let mut fields_map: HashMap<String, usize> = HashMap::new();
+    fields_map.insert("product.name".to_string(), 0usize);
+    fields_map.insert("product.color".to_string(), 1usize);
+    fields_map.insert("price".to_string(), 2usize);
+    let data_row = vec![Cell::from("Apple"),
+                        Cell::Empty,
+                        Cell::Error("#REF!".to_string())];
+    let skip = FieldFormat { empty: EmptyPolicy::Skip,
+                             ..FieldFormat::default() };
+    let unknown =
+      FieldFormat { empty:
+                      EmptyPolicy::Placeholder("unknown".to_string()),
+                    ..FieldFormat::default() };
+    let mut specs =
+      fields(&["product.name", "product.color", "price"]);
+    specs[1] = specs[1].clone().with_format(skip);
+    specs[2] = specs[2].clone().with_format(unknown);
+
+    let mut output = String::new();
+    let mut warnings = vec![];
+    generate_markdown(&data_row,
+                      &refs(&specs),
+                      &fields_map,
+                      &HashMap::new(),
+                      &mut output,
+                      &mut warnings).unwrap();
+    assert_eq!(output,
+               "# product\n\n## name\n\nApple\n\n# price\n\nunknown\n\n");
+    assert_eq!(warnings.len(), 1);
+
+    // the row fails on an empty cell of a required field
+    let fail = FieldFormat { empty: EmptyPolicy::parse("fail"),
+                             ..FieldFormat::default() };
+    specs[1] = specs[1].clone().with_format(fail);
+    let res = generate_markdown(&data_row,
+                                &refs(&specs),
+                                &fields_map,
+                                &HashMap::new(),
+                                &mut String::new(),
+                                &mut vec![]);
+    assert_eq!(res,
+               Err(Db2MdError::Render("field product.color is empty"
+                                        .to_string())));
+  }
+
+  #[test]
+  fn test_generate_markdown_schema_order()
+  {
+    // children of customer are not in adjacent columns
+    let mut fields_map: HashMap<String, usize> = HashMap::new();
+    fields_map.insert("customer.name".to_string(), 2usize);
+    fields_map.insert("price".to_string(), 0usize);
+    fields_map.insert("customer.city".to_string(), 1usize);
+    let data_row = vec![Cell::from("$3.14"),
+                        Cell::from("Chicago"),
+                        Cell::from("Alice")];
+    let mut output = String::new();
+    generate_markdown(&data_row,
+                      &refs(&fields(&["customer.name",
+                                      "customer.city",
+                                      "price",
+                                      "invalid"])),
+                      &fields_map,
+                      &HashMap::new(),
+                      &mut output,
+                      &mut vec![]).unwrap();
+    assert_eq!(output,
+               "# customer\n\n## name\n\nAlice\n\n## \
+                city\n\nChicago\n\n# price\n\n$3.14\n\n");
+  }
+
+  #[test]
+  fn test_generate_markdown_fallbacks()
+  {
+    let mut fields_map: HashMap<String, usize> = HashMap::new();
+    fields_map.insert("serial".to_string(), 0usize);
+    fields_map.insert("status".to_string(), 1usize);
+    fields_map.insert("owner".to_string(), 5usize);
+    // missing from the sheet
+    let origin = FieldSpec::new("origin", FieldType::Text)
+                   .or_default(Cell::from("unknown"));
+    let mut fallbacks = HashMap::new();
+    fallbacks.insert("serial".to_string(), vec![2usize, 3usize]);
+    let data_row = vec![Cell::Error("#N/A".to_string()),
+                        Cell::Empty,
+                        Cell::from(" "),
+                        Cell::from("SN-20240829")];
+    let specs = vec![FieldSpec::new("serial", FieldType::Text),
+           FieldSpec::new("status", FieldType::Text)
+             .or_default(Cell::from("open")),
+           FieldSpec::new("owner", FieldType::Text)
+             .or_default(Cell::from("nobody")),
+           origin];
+    let mut output = String::new();
+    let mut warnings = vec![];
+    generate_markdown(&data_row,
+                      &refs(&specs),
+                      &fields_map,
+                      &fallbacks,
+                      &mut output,
+                      &mut warnings).unwrap();
+    // the error cell is replaced and not reported
+    assert!(warnings.is_empty());
+    assert_eq!(output,
+               "# serial\n\nSN-20240829\n\n# status\n\nopen\n\n# \
+                owner\n\nnobody\n\n# origin\n\nunknown\n\n");
+  }
+
+  #[test]
+  fn test_generate_markdown_exprs()
+  {
+    let mut fields_map: HashMap<String, usize> = HashMap::new();
+    fields_map.insert("quantity".to_string(), 0usize);
+    fields_map.insert("price".to_string(), 1usize);
+    let data_row = vec![Cell::Int(3), Cell::Float(2.5)];
+    let expr = |source: &str| Expr::parse(source).unwrap();
+    let specs = vec![FieldSpec::new("quantity", FieldType::Number),
+           FieldSpec::new("price", FieldType::Number).hidden(),
+           FieldSpec::new("total", FieldType::Number)
+             .computed(expr("quantity * price")),
+           FieldSpec::new("unit", FieldType::Number)
+             .computed(expr("price / (quantity - 3)"))];
+    let mut output = String::new();
+    let mut warnings = vec![];
+    generate_markdown(&data_row,
+                      &refs(&specs),
+                      &fields_map,
+                      &HashMap::new(),
+                      &mut output,
+                      &mut warnings).unwrap();
+    // hidden fields are read by expressions but not written
+    assert_eq!(output,
+               "# quantity\n\n3\n\n# total\n\n7.5\n\n# unit\n\n\n\n");
+    assert_eq!(warnings,
+               vec![Db2MdError::Expr { field: "unit".to_string(),
+                                       message: "division by zero"
+                                                .to_string() }]);
+  }
 }
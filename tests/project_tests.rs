@@ -0,0 +1,65 @@
+#[cfg(test)]
+mod tests
+{
+  use db2md::project::*;
+  use db2md::reader::list_sheets;
+  use db2md::*;
+  use std::sync::{Arc, Mutex};
+
+  #[test]
+  fn test_load_project()
+  {
+    let project =
+      ProjectConfig::load("./tests/project.yaml").unwrap();
+    assert_eq!(project.schema.as_deref(),
+               Some("./tests/schema.yaml"));
+    assert_eq!(project.sheets,
+               vec![("Records".to_string(),
+                     Some("./tests/records_schema.yaml".to_string())),
+                    ("Vegetables".to_string(), None)]);
+
+    let sheets = list_sheets("./tests/records_test.xlsx").unwrap();
+    let sheet_schemas = project.sheet_schemas(&sheets).unwrap();
+    assert_eq!(sheet_schemas[1],
+               ("Vegetables".to_string(),
+                "./tests/schema.yaml".to_string()));
+
+    // all sheets share the default schema without a sheet list
+    let mut project = project.clone();
+    project.sheets.clear();
+    assert_eq!(project.sheet_schemas(&sheets).unwrap().len(), 3);
+
+    // a sheet missing from the workbook is an error
+    project.select(&["Archive".to_string()]);
+    assert!(project.sheet_schemas(&sheets).is_err());
+  }
+
+  #[test]
+  fn test_process_sheets()
+  {
+    let xlsx = "./tests/records_test.xlsx";
+    let project =
+      ProjectConfig::load("./tests/project.yaml").unwrap();
+    let sheets = list_sheets(xlsx).unwrap();
+    let sheet_schemas = project.sheet_schemas(&sheets).unwrap();
+    let output_dir =
+      std::env::temp_dir().join("db2md-process-sheets");
+    let options = ConvertOptions { output_dir:
+                                     output_dir.to_string_lossy()
+                                               .into_owned(),
+                                   ..ConvertOptions::default() };
+    let progress = Arc::new(Mutex::new(0.0));
+
+    let reports = process_sheets(xlsx,
+                                 &sheet_schemas,
+                                 &options,
+                                 &progress).unwrap();
+    assert_eq!(reports.len(), 2);
+    assert_eq!(reports[0].sheet, "Records");
+    assert_eq!(reports[0].written(), 4);
+    assert_eq!(reports[1].written(), 3);
+    assert!(output_dir.join("Records/ccms-doc-001.md").exists());
+    assert!(output_dir.join("Vegetables/ccms-doc-002.md").exists());
+    assert_eq!(*progress.lock().unwrap(), 100.0);
+  }
+}
@@ -2,12 +2,19 @@
 mod tests
 {
   use db2md::reader::*;
+  use db2md::yaml_parser::{map_fields_to_columns, ColumnRef};
+  use db2md::{Cell, FieldSpec, FieldType};
+
+  fn texts(row: &[Cell]) -> Vec<String>
+  {
+    row.iter().map(Cell::to_string).collect()
+  }
 
   #[test]
   fn test_read_excel()
   {
     let file_path = "./tests/fruit_test.xlsx";
-    let mut range: Vec<Vec<String>> = vec![];
+    let mut range: Vec<Vec<Cell>> = vec![];
 
     let meta =
       read_excel(file_path, &mut range).expect("Failed to read \
@@ -16,10 +23,256 @@ mod tests
     assert_eq!(meta.1, 5);
     assert_eq!(meta.2, 5);
 
-    assert_eq!(range[0][0], "APPLE");
-    assert_eq!(range[1][1], "yellow");
-    assert_eq!(range[2][2], "2024-08-29");
-    assert_eq!(range[3][3], "1.13");
-    assert_eq!(range[4][4], "Virginia");
+    assert_eq!(range[0][0].to_string(), "APPLE");
+    assert_eq!(range[1][1].to_string(), "yellow");
+    assert_eq!(range[2][2].to_string(), "2024-08-29");
+    assert_eq!(range[3][3].to_string(), "1.13");
+    assert_eq!(range[4][4].to_string(), "Virginia");
+    // values keep the type of the workbook cell
+    assert!(matches!(range[2][2], Cell::Date(_)));
+    assert_eq!(range[3][3], Cell::Float(1.13));
+  }
+
+  #[test]
+  fn test_read_excel_missing_file()
+  {
+    let mut range: Vec<Vec<Cell>> = vec![];
+    let res = read_excel("./tests/missing.xlsx", &mut range);
+    assert!(matches!(res, Err(db2md::Db2MdError::Workbook(_))));
+  }
+
+  #[test]
+  fn test_list_sheets()
+  {
+    let sheets = list_sheets("./tests/records_test.xlsx").unwrap();
+    assert_eq!(sheets, vec!["Cover", "Records", "Vegetables"]);
+  }
+
+  #[test]
+  fn test_read_excel_sheet()
+  {
+    let file_path = "./tests/records_test.xlsx";
+    let mut by_name: Vec<Vec<Cell>> = vec![];
+    let meta =
+      read_excel_sheet(file_path,
+                       &SheetSelector::Name("Records".to_string()),
+                       &mut by_name).unwrap();
+    assert_eq!(meta, ("Records".to_string(), 4, 5));
+    assert_eq!(by_name[1][1].to_string(), "router");
+    assert_eq!(by_name[1][2].to_string(), "2024-08-29");
+
+    let mut by_index: Vec<Vec<Cell>> = vec![];
+    read_excel_sheet(file_path,
+                     &"1".parse().unwrap(),
+                     &mut by_index).unwrap();
+    assert_eq!(by_name, by_index);
+
+    let mut missing: Vec<Vec<Cell>> = vec![];
+    let selector = SheetSelector::Name("Archive".to_string());
+    assert!(read_excel_sheet(file_path, &selector, &mut missing).is_err());
+  }
+
+  #[test]
+  fn test_read_csv()
+  {
+    let mut range: Vec<Vec<Cell>> = vec![];
+    let meta = read_data("./tests/fruit_test.csv",
+                         &ReadOptions::default(),
+                         &mut range).unwrap();
+    assert_eq!(meta, ("fruit_test".to_string(), 3, 4));
+    assert_eq!(range[1][2].to_string(), "1.13");
+    // quoted field keeps the delimiter
+    assert_eq!(range[2][3].to_string(), "Costa Rica, Limon");
+    assert!(detect_header(&range));
+    assert!(!detect_header(&range[1..]));
+  }
+
+  #[test]
+  fn test_read_csv_encodings()
+  {
+    let options = CsvOptions { delimiter: Some(b';'),
+                               encoding: TextEncoding::Gbk,
+                               ..CsvOptions::default() };
+    let mut gbk: Vec<Vec<Cell>> = vec![];
+    delimited::read_csv("./tests/fruit_gbk.csv", &options, &mut gbk).unwrap();
+    assert_eq!(texts(&gbk[1]), vec!["苹果", "红色", "3.5"]);
+
+    // GBK is also picked up when guessing
+    let mut auto: Vec<Vec<Cell>> = vec![];
+    let options =
+      CsvOptions { delimiter: Some(b';'), ..CsvOptions::default() };
+    delimited::read_csv("./tests/fruit_gbk.csv",
+                        &options,
+                        &mut auto).unwrap();
+    assert_eq!(gbk, auto);
+
+    // tab separated UTF-16 with BOM
+    let mut utf16: Vec<Vec<Cell>> = vec![];
+    read_data("./tests/fruit_utf16.tsv",
+              &ReadOptions::default(),
+              &mut utf16).unwrap();
+    assert_eq!(texts(&utf16[1]), vec!["苹果", "红色"]);
+    assert_eq!(list_sheets("./tests/fruit_utf16.tsv").unwrap(),
+               vec!["fruit_utf16"]);
+  }
+
+  #[test]
+  fn test_read_ods()
+  {
+    let mut range: Vec<Vec<Cell>> = vec![];
+    let meta = read_data("./tests/fruit_test.ods",
+                         &ReadOptions::default(),
+                         &mut range).unwrap();
+    assert_eq!(meta, ("Fruits".to_string(), 3, 3));
+    assert_eq!(texts(&range[1]), vec!["APPLE", "red", "1.13"]);
+    assert_eq!(list_sheets("./tests/fruit_test.ods").unwrap(),
+               vec!["Fruits"]);
+  }
+
+  #[test]
+  fn test_read_sqlite()
+  {
+    let db = "./tests/ccms_test.db";
+    assert_eq!(list_sheets(db).unwrap(),
+               vec!["complaints", "engineers"]);
+    assert!(has_column_names(db));
+
+    let options =
+      ReadOptions { sheet:
+                      SheetSelector::Name("complaints".to_string()),
+                    ..ReadOptions::default() };
+    let mut table: Vec<Vec<Cell>> = vec![];
+    let meta = read_data(db, &options, &mut table).unwrap();
+    assert_eq!(meta, ("complaints".to_string(), 4, 6));
+    assert_eq!(texts(&table[0]),
+               vec!["id", "sbu", "product", "date", "price",
+                    "customer"]);
+    assert_eq!(table[1][4].to_string(), "12.5");
+    // NULL is an empty cell
+    assert_eq!(table[3][4], Cell::Empty);
+
+    let sql =
+      "SELECT product AS name FROM complaints WHERE sbu = 'FIT'";
+    let options = ReadOptions { query: Some(sql.to_string()),
+                                ..ReadOptions::default() };
+    let mut query: Vec<Vec<Cell>> = vec![];
+    read_data(db, &options, &mut query).unwrap();
+    assert_eq!(query,
+               vec![vec![Cell::from("name")],
+                    vec![Cell::from("router")],
+                    vec![Cell::from("modem")]]);
+  }
+
+  #[test]
+  fn test_read_json()
+  {
+    let mut array: Vec<Vec<Cell>> = vec![];
+    let meta = read_data("./tests/records_test.json",
+                         &ReadOptions::default(),
+                         &mut array).unwrap();
+    assert_eq!(meta, ("records_test".to_string(), 3, 6));
+    assert_eq!(texts(&array[0]),
+               vec!["id",
+                    "customer.name",
+                    "customer.department.product",
+                    "customer.department.price",
+                    "tags",
+                    "resolved"]);
+    assert_eq!(array[1][2].to_string(), "router");
+    assert_eq!(array[1][4].to_string(), "urgent, network");
+    // keys missing from a record are empty cells
+    assert_eq!(array[2][3].to_string(), "");
+    assert_eq!(array[2][5].to_string(), "true");
+    assert!(has_column_names("./tests/records_test.json"));
+
+    // one record per line, same rows
+    let mut lines: Vec<Vec<Cell>> = vec![];
+    read_data("./tests/records_test.ndjson",
+              &ReadOptions::default(),
+              &mut lines).unwrap();
+    assert_eq!(array, lines);
+
+    // rows are appended after those already read
+    let mut appended = vec![vec![Cell::from("before")]];
+    let meta = read_data("./tests/records_test.json",
+                         &ReadOptions::default(),
+                         &mut appended).unwrap();
+    assert_eq!(meta.1, 3);
+    assert_eq!(appended[1..], array[..]);
+  }
+
+  #[test]
+  fn test_read_times()
+  {
+    let mut range: Vec<Vec<Cell>> = vec![];
+    read_data("./tests/times_test.xlsx",
+              &ReadOptions::default(),
+              &mut range).unwrap();
+    // the time of day is kept with the date
+    assert!(matches!(range[1][1], Cell::DateTime(_)));
+    assert_eq!(range[1][1].to_string(), "2024-08-29 14:05:00");
+    assert!(matches!(range[1][2], Cell::Time(_)));
+    assert_eq!(range[1][2].to_string(), "08:30:00");
+    assert!(matches!(range[1][3], Cell::Duration(_)));
+    assert_eq!(range[1][3].to_string(), "27:05:00");
+  }
+
+  #[test]
+  fn test_header_layout()
+  {
+    let mut range: Vec<Vec<Cell>> = vec![];
+    read_data("./tests/banner_test.xlsx",
+              &ReadOptions::default(),
+              &mut range).unwrap();
+    let layout = HeaderLayout { row: 3, rows: 2, skip: 1 };
+    // merged group titles spread over their columns
+    assert_eq!(layout.headers(&range),
+               vec!["ticket",
+                    "customer.name",
+                    "customer.department",
+                    "product.name",
+                    "product.price"]);
+    assert!(!layout.is_table_row(1, true));
+    assert!(!layout.is_table_row(4, true));
+    assert!(!layout.is_table_row(5, true));
+    assert!(layout.is_table_row(6, true));
+    // without a header the skipped rows follow the banner
+    assert!(!layout.is_table_row(3, false));
+    assert!(layout.is_table_row(4, false));
+
+    // a group ends with its parent group
+    let rows =
+      vec![vec![Cell::from("a"), Cell::Empty, Cell::from("b")],
+           vec![Cell::from("x"), Cell::Empty, Cell::Empty],
+           vec![Cell::from("1"), Cell::from("2"), Cell::from("3")]];
+    assert_eq!(merge_header_rows(&rows),
+               vec!["a.x.1", "a.x.2", "b.3"]);
+  }
+
+  #[test]
+  fn test_read_excel_offset()
+  {
+    // data starts at B2, below a blank title row
+    let mut sheet: Vec<Vec<Cell>> = vec![];
+    let meta = read_data("./tests/offset_test.xlsx",
+                         &ReadOptions::default(),
+                         &mut sheet).unwrap();
+    assert_eq!(meta, ("Offset".to_string(), 4, 3));
+    assert_eq!(texts(&sheet[0]), vec!["", "", ""]);
+    assert_eq!(texts(&sheet[2]), vec!["", "apple", "3"]);
+
+    // column letters count from A
+    let specs = [FieldSpec::new("cost", FieldType::Number)
+                   .bound_to(ColumnRef::Name("C".to_string()))];
+    let fields = specs.iter().collect::<Vec<_>>();
+    let field_map =
+      map_fields_to_columns(&fields, &header_names(&sheet), &mut vec![]);
+    assert_eq!(sheet[3][field_map["cost"]], Cell::Float(4.5));
+
+    // the header row counts the blank title row too
+    let layout = HeaderLayout { row: 1, ..HeaderLayout::default() };
+    assert_eq!(layout.headers(&sheet), vec!["", "fruit", "price"]);
+    assert!(!layout.is_table_row(1, true));
+    assert!(layout.is_table_row(2, true));
   }
 }
@@ -0,0 +1,43 @@
+#[cfg(test)]
+mod tests
+{
+  use db2md::*;
+
+  #[test]
+  fn test_report_to_json()
+  {
+    let warning =
+      Db2MdError::Coerce { field: "price".to_string(),
+                           message:
+                             "'n/a' is not a number".to_string() };
+    let written = RowReport { row: 0,
+                              outcome: RowOutcome::Written,
+                              output:
+                                Some("_md/doc-000.md".to_string()),
+                              error: None,
+                              warnings: vec![warning] };
+    let failed =
+      RowReport { row: 1,
+                  outcome: RowOutcome::Failed,
+                  output: None,
+                  error: Some(Db2MdError::Io { path: "_md/doc-001.md"
+                                                   .to_string(),
+                                               message: "denied"
+                                                 .to_string() }),
+                  warnings: vec![] };
+    let report = ConversionReport { rows: vec![written, failed],
+                                    ..ConversionReport::default() };
+    assert_eq!(report.written(), 1);
+    assert_eq!(report.warnings(), 1);
+    assert!(!report.is_success());
+
+    let json: serde_json::Value =
+      serde_json::from_str(&report.to_json()).unwrap();
+    assert_eq!(json["rows"][0]["outcome"], "written");
+    assert_eq!(json["rows"][0]["warnings"][0],
+               "cannot convert field price: 'n/a' is not a number");
+    assert_eq!(json["rows"][1]["outcome"], "failed");
+    assert_eq!(json["rows"][1]["error"],
+               "cannot access '_md/doc-001.md': denied");
+  }
+}
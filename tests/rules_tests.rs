@@ -0,0 +1,89 @@
+#[cfg(test)]
+mod tests
+{
+  use db2md::rules::*;
+  use db2md::{Cell, Db2MdError, FieldSpec, FieldType};
+  use std::collections::HashMap;
+  use yaml_rust2::YamlLoader;
+
+  fn rules(yaml: &str) -> FieldRules
+  {
+    let docs = YamlLoader::load_from_str(yaml).unwrap();
+    let mut rules = FieldRules::default();
+    for (key, value) in docs[0].as_hash().unwrap() {
+      rules.set(key.as_str().unwrap(), value).unwrap();
+    }
+    rules
+  }
+
+  #[test]
+  fn test_check_rules()
+  {
+    let status =
+      rules("{required: true, enum: [open, closed, pending]}");
+    assert!(status.check(Some(&Cell::from(" open "))).is_empty());
+    assert_eq!(status.check(Some(&Cell::from("done"))),
+               vec!["'done' is not one of open, closed, pending"]);
+    assert_eq!(status.check(Some(&Cell::Error("#N/A".to_string()))),
+               vec!["is required"]);
+    assert_eq!(status.check(None), vec!["is required"]);
+
+    // the whole value has to match
+    let serial = rules("{regex: 'SN-\\d{8}', max_length: 11}");
+    assert!(serial.check(Some(&Cell::from("SN-20240829")))
+                  .is_empty());
+    assert_eq!(serial.check(Some(&Cell::from("SN-202408290"))),
+               vec!["'SN-202408290' does not match SN-\\d{8}",
+                    "'SN-202408290' is longer than 11 characters"]);
+    // empty cells only break `required`
+    assert!(serial.check(Some(&Cell::Empty)).is_empty());
+
+    let price = rules("{min: 0, max: 99.5}");
+    assert!(price.check(Some(&Cell::Int(0))).is_empty());
+    assert!(price.check(Some(&Cell::from("99.5"))).is_empty());
+    assert_eq!(price.check(Some(&Cell::Float(-0.5))),
+               vec!["-0.5 is below the minimum 0"]);
+    assert_eq!(price.check(Some(&Cell::from("n/a"))),
+               vec!["'n/a' is no number"]);
+  }
+
+  #[test]
+  fn test_invalid_rules()
+  {
+    let docs = YamlLoader::load_from_str("[1, '(', x]").unwrap();
+    let mut rules = FieldRules::default();
+    assert!(rules.set("regex", &docs[0][1]).is_err());
+    assert!(rules.set("min", &docs[0][2]).is_err());
+    assert!(rules.set("max_length", &docs[0][0]).is_ok());
+    assert!(rules.set("enum", &docs[0]).is_ok());
+    assert_eq!(rules.one_of, vec!["1", "(", "x"]);
+    assert_eq!(RulePolicy::from_name("Skip"),
+               Some(RulePolicy::Skip));
+    assert_eq!(RulePolicy::from_name("ignore"), None);
+  }
+
+  #[test]
+  fn test_check_row()
+  {
+    let required = rules("{required: true}");
+    let specs = [FieldSpec::new("name", FieldType::Text)
+                   .with_rules(required.clone()),
+                 FieldSpec::new("missing", FieldType::Text)
+                   .with_rules(required.clone()),
+                 FieldSpec::new("note", FieldType::Text)
+                   .with_rules(required)
+                   .or_default(Cell::from("none"))];
+    let fields = specs.iter().collect::<Vec<_>>();
+    let mut field_map = HashMap::new();
+    field_map.insert("name".to_string(), 0);
+    // a required field missing from the sheet breaks every row
+    let broken = check_row(&[Cell::from("Alice")],
+                           &fields,
+                           &field_map,
+                           &HashMap::new());
+    assert_eq!(broken,
+               vec![Db2MdError::Rule { field: "missing".to_string(),
+                                       message: "is required"
+                                                .to_string() }]);
+  }
+}
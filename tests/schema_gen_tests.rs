@@ -0,0 +1,95 @@
+#[cfg(test)]
+mod tests
+{
+  use chrono::NaiveDate;
+  use db2md::reader::HeaderLayout;
+  use db2md::schema_gen::*;
+  use db2md::schema_lint::lint_schema;
+  use db2md::yaml_parser::*;
+  use db2md::{Cell, FieldType};
+  use yaml_rust2::YamlLoader;
+
+  #[test]
+  fn test_infer_type()
+  {
+    let date = NaiveDate::from_ymd_opt(2024, 8, 29).unwrap();
+    let datetime = date.and_hms_opt(14, 5, 0).unwrap();
+    let cells = [Cell::Int(3), Cell::Empty, Cell::Float(0.5)];
+    assert_eq!(infer_type(cells.iter()), FieldType::Number);
+    let cells = [Cell::Date(date), Cell::DateTime(datetime)];
+    assert_eq!(infer_type(cells.iter()), FieldType::DateTime);
+    // error cells say nothing about the type
+    let cells = [Cell::Bool(true), Cell::Error("#N/A".to_string())];
+    assert_eq!(infer_type(cells.iter()), FieldType::Bool);
+    let cells = [Cell::Int(3), Cell::from("n/a")];
+    assert_eq!(infer_type(cells.iter()), FieldType::Text);
+    assert_eq!(infer_type([].iter()), FieldType::Text);
+  }
+
+  #[test]
+  fn test_infer_schema()
+  {
+    let rows = vec![vec![Cell::from("Customer Name"),
+                         Cell::from("Customer City"),
+                         Cell::from("price"),
+                         Cell::from("price"),
+                         Cell::Empty],
+                    vec![Cell::from("Alice"),
+                         Cell::from("Chicago"),
+                         Cell::Float(3.5),
+                         Cell::Int(4),
+                         Cell::Bool(true)]];
+    let layout = HeaderLayout::default();
+    let schema = infer_schema(&rows, &layout, 10, true).unwrap();
+    let yaml = schema_to_string(&schema).unwrap();
+    assert_eq!(yaml,
+               "Customer:\n  Name:\n    column: Customer Name\n    type: \
+                text\n  City:\n    column: Customer City\n    type: \
+                text\nprice:\n  column: 2\n  type: number\nprice_2:\n  \
+                column: 3\n  type: number\ncolumn_4:\n  column: 4\n  \
+                type: bool\n");
+
+    // the schema maps every field to its own column
+    let docs = YamlLoader::load_from_str(&yaml).unwrap();
+    let schema = Schema::from_yaml(&docs[0]).unwrap();
+    let mut invalids = vec![];
+    let field_map = map_fields_to_columns(&schema.fields(),
+                                          &layout.headers(&rows),
+                                          &mut invalids);
+    assert!(invalids.is_empty());
+    assert_eq!(field_map["Customer.City"], 1);
+    assert_eq!(field_map["price_2"], 3);
+    assert_eq!(field_map["column_4"], 4);
+  }
+
+  #[test]
+  fn test_infer_schema_option_names()
+  {
+    let rows = vec![vec![Cell::from("ticket_type"),
+                         Cell::from("ticket_source"),
+                         Cell::from("ticket.max"),
+                         Cell::from("id")],
+                    vec![Cell::from("bug"),
+                         Cell::from("mail"),
+                         Cell::Int(3),
+                         Cell::Int(1)]];
+    let layout = HeaderLayout::default();
+    let schema = infer_schema(&rows, &layout, 10, true).unwrap();
+    let yaml = schema_to_string(&schema).unwrap();
+    assert_eq!(yaml,
+               "ticket_type: text\nticket_source: text\nticket.max: \
+                number\nid: number\n");
+
+    // names of options are no sections db2md would read as a field
+    assert!(lint_schema(&yaml).is_empty());
+    let docs = YamlLoader::load_from_str(&yaml).unwrap();
+    let schema = Schema::from_yaml(&docs[0]).unwrap();
+    let mut invalids = vec![];
+    let field_map = map_fields_to_columns(&schema.fields(),
+                                          &layout.headers(&rows),
+                                          &mut invalids);
+    assert!(invalids.is_empty());
+    assert_eq!(field_map["ticket_source"], 1);
+    assert_eq!(field_map["ticket.max"], 2);
+  }
+}
@@ -0,0 +1,140 @@
+#[cfg(test)]
+mod tests
+{
+  use db2md::schema_lint::*;
+  use std::collections::HashMap;
+
+  fn messages(issues: &[LintIssue]) -> Vec<String>
+  {
+    issues.iter().map(|i| i.to_string()).collect()
+  }
+
+  #[test]
+  fn test_lint_schema()
+  {
+    let yaml = "_options:
+  thousand: ','
+order:
+  price: nmber
+  amount:
+    type: number
+    precison: 2
+  items: [a, b]
+  total:
+customer:
+  name: text
+  1: text
+supplier:
+  name: {type: text, precision: -1}
+";
+    assert_eq!(messages(&lint_schema(yaml)),
+               vec!["1:1: warning: unknown option thousand of _options",
+                    "4:3: error: unknown type 'nmber' of order.price, \
+                     expected text, number, date, datetime, time, \
+                     duration or bool",
+                    "5:3: warning: order.amount is read as a section \
+                     because of unknown options [\"precison\"]",
+                    "7:5: error: field order.amount.precison has no \
+                     type name but 2",
+                    "8:3: error: order.items is a list, which a schema \
+                     does not support",
+                    "9:3: error: field order.total has no type",
+                    "12:3: error: key 1 is no text, quote it",
+                    "14:3: error: invalid precision of supplier.name: -1",
+                    "14:3: warning: supplier.name has the same name as \
+                     customer.name, bind one of them with `column:`"]);
+    assert!(lint_schema("organization:\n  sbu: text\n").is_empty());
+  }
+
+  #[test]
+  fn test_lint_schema_rules()
+  {
+    let yaml = "_options:
+  on_invalid: ignore
+  required: true
+status:
+  type: text
+  enum: open
+serial: {type: text, regex: 'SN-(', max_length: 11}
+price: {min: 0, max: 10}
+";
+    assert_eq!(messages(&lint_schema(yaml)),
+               vec!["1:1: error: invalid on_invalid of _options: ignore",
+                    "1:1: warning: unknown option required of _options",
+                    "4:1: error: invalid enum of status: expected a \
+                     list of values",
+                    "7:1: error: invalid regex of serial: unclosed \
+                     group",
+                    "8:1: warning: price is read as a section, give its \
+                     rules a type",
+                    "8:9: error: field price.min has no type name but 0",
+                    "8:17: error: field price.max has no type name but \
+                     10"]);
+  }
+
+  #[test]
+  fn test_lint_schema_sources()
+  {
+    let yaml = "_options:
+  default: none
+serial: {column: A, source: [B, C]}
+amount: {source: B, default: [0]}
+";
+    assert_eq!(messages(&lint_schema(yaml)),
+               vec!["1:1: warning: unknown option default of _options",
+                    "3:1: warning: serial has both column and source, \
+                     the first source is taken",
+                    "4:1: error: invalid source of amount: B",
+                    "4:1: error: invalid default of amount: a list"]);
+  }
+
+  #[test]
+  fn test_lint_schema_exprs()
+  {
+    let yaml = "price: number
+total: {type: number, expr: price * quantity, hidden: yes}
+label: {expr: \"upper(price\"}
+double: {expr: total * 2}
+";
+    assert_eq!(messages(&lint_schema(yaml)),
+               vec!["2:1: error: invalid hidden of total: yes",
+                    "3:1: error: invalid expr of label: expected ',' \
+                     or ')' at the end",
+                    "2:1: error: expr of total reads quantity, which \
+                     is no field with a column",
+                    "4:1: error: expr of double reads total, which is \
+                     no field with a column"]);
+  }
+
+  #[test]
+  fn test_lint_schema_documents()
+  {
+    let issues = lint_schema("");
+    assert_eq!(messages(&issues), vec!["error: empty document"]);
+    assert!(issues[0].is_error());
+    let issues = lint_schema("a: text\n  b: [\n");
+    assert_eq!(issues.len(), 1);
+    assert_eq!(issues[0].position.map(|p| p.0), Some(2));
+    assert_eq!(messages(&lint_schema("- text\n")),
+               vec!["error: the schema is no mapping of sections and \
+                     fields"]);
+  }
+
+  #[test]
+  fn test_unmapped_columns()
+  {
+    let mut field_map = HashMap::new();
+    field_map.insert("name".to_string(), 0);
+    field_map.insert("price".to_string(), 2);
+    let headers = vec!["name".to_string(), "Unit".to_string()];
+    let mut fallbacks = HashMap::new();
+    assert_eq!(messages(&unmapped_columns(&field_map, &fallbacks,
+                                          &headers, 4)),
+               vec!["warning: column 1 'Unit' is used by no field",
+                    "warning: column 3 is used by no field"]);
+    fallbacks.insert("name".to_string(), vec![3]);
+    assert_eq!(unmapped_columns(&field_map, &fallbacks, &headers,
+                                4).len(),
+               1);
+  }
+}
@@ -3,8 +3,16 @@ mod tests
 {
   use db2md::reader::*;
   use db2md::yaml_parser::*;
+  use db2md::{Cell, Db2MdError, FieldRules, FieldType, RulePolicy};
   use yaml_rust2::YamlLoader;
 
+  fn text_fields(paths: &[&str]) -> Vec<FieldSpec>
+  {
+    paths.iter()
+         .map(|p| FieldSpec::new(p, FieldType::Text))
+         .collect()
+  }
+
   #[test]
   fn test_parse_yaml_schema_from_file()
   {
@@ -14,8 +22,7 @@ mod tests
                  "text");
       assert_eq!(schema["date"].as_str().unwrap(), "date");
     } else {
-      println!("error reading yaml file");
-      assert!(false)
+      panic!("error reading yaml file")
     }
   }
 
@@ -47,20 +54,19 @@ mod tests
   fn test_field_map_no_header()
   {
     let yaml_file = "./tests/schema.yaml";
-    if let Ok(schema) = parse_yaml_schema(yaml_file) {
-      let mut fields = Vec::new();
-      extract_fields(&schema, "", &mut fields);
+    if let Ok(schema) = Schema::load(yaml_file) {
       let headers = Vec::new();
       let mut invalids = vec![];
-      let hmap =
-        map_fields_to_columns(&fields, &headers, &mut invalids);
+      let hmap = map_fields_to_columns(&schema.fields(),
+                                       &headers,
+                                       &mut invalids);
       assert_eq!(hmap["organization.sbu"], 0usize);
       assert_eq!(hmap["organization.product"], 1usize);
       assert_eq!(hmap["date"], 2usize);
       assert_eq!(hmap["complaint.price"], 3usize);
       assert_eq!(hmap["complaint.customer"], 4usize);
     } else {
-      assert!(false);
+      panic!("error reading yaml file");
     }
   }
 
@@ -70,14 +76,17 @@ mod tests
     let xslx = "./tests/fruit_test.xlsx";
     let mut sheet = vec![];
     read_excel(xslx, &mut sheet).unwrap();
-    let headers = &sheet[0];
-    let fields = vec!["product.APPLE".to_string(),
-                      "product.color.red".to_string(),
-                      "2024-10-11".to_string(),
-                      "312".to_string(),
-                      "California".to_string()];
+    let headers = &header_names(&sheet);
+    let fields = text_fields(&["product.APPLE",
+                               "product.color.red",
+                               "2024-10-11",
+                               "312",
+                               "California"]);
     let mut invalids = vec![];
-    let hmap = map_fields_to_columns(&fields, headers, &mut invalids);
+    let hmap = map_fields_to_columns(&fields.iter()
+                                            .collect::<Vec<_>>(),
+                                     headers,
+                                     &mut invalids);
     assert_eq!(hmap["product.APPLE"],
                headers.iter()
                       .position(|h| h.as_str() == "APPLE")
@@ -94,4 +103,298 @@ mod tests
                    .position(|h| h.as_str() == "312")
                    .is_none());
   }
+
+  #[test]
+  fn test_field_map_dotted_header()
+  {
+    let fields = text_fields(&["customer.department.product",
+                               "customer.name",
+                               "report.id"]);
+    let headers = vec!["id".to_string(),
+                       "customer.name".to_string(),
+                       "customer.department.product".to_string()];
+    let mut invalids = vec![];
+    let hmap = map_fields_to_columns(&fields.iter()
+                                            .collect::<Vec<_>>(),
+                                     &headers,
+                                     &mut invalids);
+    assert_eq!(hmap["customer.department.product"], 2);
+    assert_eq!(hmap["customer.name"], 1);
+    // last segment still matches a plain header
+    assert_eq!(hmap["report.id"], 0);
+    let field =
+      FieldSpec::new("organization.customer.name", FieldType::Text);
+    let hmap =
+      map_fields_to_columns(&[&field], &headers, &mut invalids);
+    assert_eq!(hmap["organization.customer.name"], 1);
+    assert!(invalids.is_empty());
+  }
+
+  #[test]
+  fn test_extract_field_formats()
+  {
+    let yaml_str = "
+        _options:
+          thousands: ','
+          date_format: '%d/%m/%Y'
+        organization:
+          sbu: text
+        date: date
+        price:
+          type: number
+          precision: 2
+        opened:
+          type: datetime
+          format: '%Y-%m-%d %H:%M'
+        shipment:
+          type: text
+          weight: number
+        ";
+    let docs = YamlLoader::load_from_str(yaml_str).unwrap();
+    let mut fields = Vec::new();
+    extract_fields(&docs[0], "", &mut fields);
+    // options are no section, a hash with a type is a field
+    assert_eq!(fields,
+               vec!["organization.sbu",
+                    "date",
+                    "price",
+                    "opened",
+                    "shipment.type",
+                    "shipment.weight"]);
+
+    let schema = Schema::from_yaml(&docs[0]).unwrap();
+    assert_eq!(schema.field_paths(), fields);
+    let format = |path| schema.field(path).unwrap().format.clone();
+    assert_eq!(format("date").kind, FieldType::Date);
+    assert_eq!(format("price").kind, FieldType::Number);
+    assert_eq!(format("price").precision, Some(2));
+    assert_eq!(format("price").thousands.as_deref(), Some(","));
+    assert_eq!(format("shipment.weight").kind, FieldType::Number);
+    assert_eq!(format("organization.sbu").kind, FieldType::Text);
+    assert_eq!(format("date").date_format, "%d/%m/%Y");
+    assert_eq!(format("opened").kind, FieldType::DateTime);
+    assert_eq!(format("opened").format.as_deref(),
+               Some("%Y-%m-%d %H:%M"));
+    assert_eq!(schema.defaults.thousands.as_deref(), Some(","));
+  }
+
+  #[test]
+  fn test_field_map_columns()
+  {
+    let yaml_str = "
+        order:
+          price: {column: \"Unit Price\", type: number}
+          total: {column: D}
+        refund:
+          price: {column: 1}
+        note: text
+        ";
+    let docs = YamlLoader::load_from_str(yaml_str).unwrap();
+    let schema = Schema::from_yaml(&docs[0]).unwrap();
+    let fields = schema.fields();
+    let column = |path| schema.field(path).unwrap().column.clone();
+    assert_eq!(column("order.price"),
+               Some(ColumnRef::Name("Unit Price".to_string())));
+    assert_eq!(column("refund.price"), Some(ColumnRef::Index(1)));
+    assert_eq!(column("note"), None);
+
+    // two fields named price no longer collide
+    let headers = vec!["note".to_string(),
+                       "Refund".to_string(),
+                       "Unit Price".to_string(),
+                       "Total".to_string()];
+    let mut invalids = vec![];
+    let hmap =
+      map_fields_to_columns(&fields, &headers, &mut invalids);
+    assert_eq!(hmap["order.price"], 2);
+    assert_eq!(hmap["order.total"], 3);
+    assert_eq!(hmap["refund.price"], 1);
+    assert_eq!(hmap["note"], 0);
+    assert!(invalids.is_empty());
+
+    // without a header a column title cannot be found, unbound fields
+    // take the columns no field is bound to
+    let hmap = map_fields_to_columns(&fields, &[], &mut invalids);
+    assert_eq!(hmap["order.total"], 3);
+    assert_eq!(hmap["refund.price"], 1);
+    assert_eq!(hmap["note"], 0);
+    assert_eq!(invalids, vec!["order.price"]);
+  }
+
+  #[test]
+  fn test_field_map_normalized()
+  {
+    assert_eq!(normalize_header(" Unit  Price "), "unit price");
+    assert_eq!(normalize_header("UNIT_PRICE"), "unit price");
+    assert_eq!(normalize_header("Ｕｎｉｔ\u{3000}Ｐｒｉｃｅ"),
+               "unit price");
+
+    let fields = text_fields(&["order.unit-price",
+                               "customer-name",
+                               "order.quantity"]);
+    let headers = vec!["Customer Name".to_string(),
+                       "ＵＮＩＴ＿ＰＲＩＣＥ".to_string(),
+                       "Qty".to_string(),
+                       "Quantiy".to_string()];
+    let mut invalids = vec![];
+    let hmap = map_fields_to_columns(&fields.iter()
+                                            .collect::<Vec<_>>(),
+                                     &headers,
+                                     &mut invalids);
+    assert_eq!(hmap["order.unit-price"], 1);
+    assert_eq!(hmap["customer-name"], 0);
+    assert_eq!(invalids, vec!["order.quantity"]);
+    assert_eq!(suggest_headers("order.quantity", &headers),
+               vec!["Quantiy"]);
+    assert!(suggest_headers("shipment", &headers).is_empty());
+  }
+
+  #[test]
+  fn test_schema_model()
+  {
+    let yaml_str = "
+        organization:
+          sbu: text
+        price: {type: number, column: C}
+        organization2:
+          region:
+            city: text
+        ";
+    let docs = YamlLoader::load_from_str(yaml_str).unwrap();
+    let mut schema = Schema::from_yaml(&docs[0]).unwrap();
+    let SchemaItem::Section(section) = &schema.items[0] else {
+      panic!("organization is no section");
+    };
+    assert_eq!(section.name, "organization");
+    assert_eq!(section.items.len(), 1);
+    assert_eq!(schema.field("price").unwrap().kind(),
+               FieldType::Number);
+
+    // fields added later join their sections or create them
+    schema.add_field(FieldSpec::new("organization.product",
+                                    FieldType::Text));
+    schema.add_field(FieldSpec::new("shipment.weight",
+                                    FieldType::Number)
+                       .bound_to(ColumnRef::Index(4)));
+    assert_eq!(schema.field_paths(),
+               vec!["organization.sbu",
+                    "organization.product",
+                    "price",
+                    "organization2.region.city",
+                    "shipment.weight"]);
+    assert_eq!(schema.items.len(), 4);
+    assert_eq!(schema.field("shipment.weight").unwrap().column,
+               Some(ColumnRef::Index(4)));
+  }
+
+  #[test]
+  fn test_schema_rules()
+  {
+    let yaml_str = "
+        _options:
+          on_invalid: skip
+        status: {type: text, required: true, enum: [open, closed]}
+        serial: {type: text, regex: 'SN-\\d{8}'}
+        note: text
+        ";
+    let docs = YamlLoader::load_from_str(yaml_str).unwrap();
+    let schema = Schema::from_yaml(&docs[0]).unwrap();
+    assert_eq!(schema.on_invalid, RulePolicy::Skip);
+    let status = &schema.field("status").unwrap().rules;
+    assert!(status.required);
+    assert_eq!(status.one_of, vec!["open", "closed"]);
+    let serial = &schema.field("serial").unwrap().rules;
+    assert_eq!(serial.pattern.as_ref().map(|p| p.as_str()),
+               Some("SN-\\d{8}"));
+    assert_eq!(schema.field("note").unwrap().rules,
+               FieldRules::default());
+
+    let docs =
+      YamlLoader::load_from_str("id: {type: text, max_length: -1}")
+        .unwrap();
+    assert_eq!(Schema::from_yaml(&docs[0]),
+               Err(Db2MdError::Schema("invalid max_length of id: \
+                                       expected a positive integer"
+                                                          .to_string())));
+  }
+
+  #[test]
+  fn test_schema_sources()
+  {
+    let yaml_str = "
+        serial: {type: text, source: [Serial, SN, 4], default: none}
+        amount: {source: [B]}
+        count: {type: number, default: 0}
+        ";
+    let docs = YamlLoader::load_from_str(yaml_str).unwrap();
+    let schema = Schema::from_yaml(&docs[0]).unwrap();
+    let serial = schema.field("serial").unwrap();
+    assert_eq!(serial.column,
+               Some(ColumnRef::Name("Serial".to_string())));
+    assert_eq!(serial.fallbacks,
+               vec![ColumnRef::Name("SN".to_string()),
+                    ColumnRef::Index(4)]);
+    assert_eq!(serial.default, Some(Cell::from("none")));
+    assert_eq!(schema.field("amount").unwrap().fallbacks, vec![]);
+    assert_eq!(schema.field("count").unwrap().default,
+               Some(Cell::Int(0)));
+
+    // fallbacks missing from the sheet are left out
+    let headers = vec!["SN".to_string(), "Serial".to_string()];
+    let fallbacks = map_fallback_columns(&schema.fields(), &headers);
+    assert_eq!(fallbacks["serial"], vec![0]);
+    assert!(!fallbacks.contains_key("amount"));
+
+    // the chain starts at the first source the sheet has, a default
+    // stands in for a field it lacks
+    let yaml_str = "
+        serial: {type: text, source: [Old, Serial, SN]}
+        owner: {type: text, source: [Owner], default: nobody}
+        ";
+    let docs = YamlLoader::load_from_str(yaml_str).unwrap();
+    let schema = Schema::from_yaml(&docs[0]).unwrap();
+    let headers =
+      vec!["SN".to_string(), "Serial".to_string(), "name".to_string()];
+    let mut invalids = vec![];
+    let field_map =
+      map_fields_to_columns(&schema.fields(), &headers, &mut invalids);
+    assert_eq!(field_map["serial"], 1);
+    assert!(!field_map.contains_key("owner"));
+    assert!(invalids.is_empty());
+
+    let docs =
+      YamlLoader::load_from_str("id: {source: []}").unwrap();
+    assert!(Schema::from_yaml(&docs[0]).is_err());
+  }
+
+  #[test]
+  fn test_schema_exprs()
+  {
+    let yaml_str = "
+        first: text
+        last: {type: text, hidden: true}
+        full_name: {type: text, expr: \"first & ' ' & last\"}
+        age: number
+        ";
+    let docs = YamlLoader::load_from_str(yaml_str).unwrap();
+    let schema = Schema::from_yaml(&docs[0]).unwrap();
+    assert!(schema.field("last").unwrap().hidden);
+    let full_name = schema.field("full_name").unwrap();
+    assert_eq!(full_name.expr.as_ref().map(|e| e.as_str()),
+               Some("first & ' ' & last"));
+
+    // computed fields take no column, even by position
+    let field_map =
+      map_fields_to_columns(&schema.fields(), &[], &mut vec![]);
+    assert_eq!(field_map["age"], 2);
+    assert!(!field_map.contains_key("full_name"));
+
+    for yaml_str in ["a: {expr: 'b + 1'}",
+                     "a: {expr: '1 +'}",
+                     "a: text\nb: {expr: a}\nc: {expr: b}"]
+    {
+      let docs = YamlLoader::load_from_str(yaml_str).unwrap();
+      assert!(Schema::from_yaml(&docs[0]).is_err());
+    }
+  }
 }